@@ -0,0 +1,27 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Native;
+
+use euclid::RigidTransform3D;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/anchors/#xranchor
+pub struct AnchorId(pub u32);
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The coordinate space of an anchor
+pub struct AnchorSpace;
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The pose of a tracked anchor for a given frame
+pub struct AnchorPose {
+    pub id: AnchorId,
+    /// The pose of the anchor in native coordinates,
+    /// or None if the anchor is not currently being tracked
+    pub pose: Option<RigidTransform3D<f32, AnchorSpace, Native>>,
+}
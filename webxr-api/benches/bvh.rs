@@ -0,0 +1,135 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Compares hit testing a large mock world with a `Bvh` against a linear scan.
+//!
+//! Run with `cargo bench -p webxr-api --bench bvh`.
+
+use euclid::{Point3D, Vector3D};
+use std::time::{Duration, Instant};
+use webxr_api::{Bvh, Native, Ray, Triangle};
+
+// A 224 x 224 grid is a little over 100k triangles, about the size of a scanned room
+const GRID_SIZE: usize = 224;
+const RAY_COUNT: usize = 1000;
+
+/// A bumpy floor, so that the triangles aren't all coplanar
+fn terrain() -> Vec<Triangle> {
+    let height = |x: usize, z: usize| ((x as f32 * 0.3).sin() + (z as f32 * 0.2).cos()) * 0.1;
+    let point = |x: usize, z: usize| {
+        Point3D::new(
+            x as f32 / GRID_SIZE as f32 * 10. - 5.,
+            height(x, z),
+            z as f32 / GRID_SIZE as f32 * 10. - 5.,
+        )
+    };
+    let mut triangles = Vec::with_capacity(GRID_SIZE * GRID_SIZE * 2);
+    for x in 0..GRID_SIZE {
+        for z in 0..GRID_SIZE {
+            let (a, b, c, d) = (
+                point(x, z),
+                point(x + 1, z),
+                point(x, z + 1),
+                point(x + 1, z + 1),
+            );
+            triangles.push(Triangle {
+                first: a,
+                second: c,
+                third: b,
+            });
+            triangles.push(Triangle {
+                first: b,
+                second: c,
+                third: d,
+            });
+        }
+    }
+    triangles
+}
+
+/// Rays pointing down at the floor from head height, from a fixed pseudo-random sequence
+fn rays() -> Vec<Ray<Native>> {
+    let mut seed: u32 = 0x1234_5678;
+    let mut random = move || {
+        // https://en.wikipedia.org/wiki/Linear_congruential_generator
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 8) as f32 / (1 << 24) as f32 * 2. - 1.
+    };
+    (0..RAY_COUNT)
+        .map(|_| Ray {
+            origin: Vector3D::new(random() * 4., 1.6, random() * 4.),
+            direction: Vector3D::new(random() * 0.5, -1., random() * 0.5).normalize(),
+        })
+        .collect()
+}
+
+fn time<R>(name: &str, iterations: usize, mut f: impl FnMut() -> R) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        std::hint::black_box(f());
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<24} {:>12.3?} total, {:>12.3?} per iteration",
+        name,
+        elapsed,
+        elapsed / iterations as u32
+    );
+    elapsed
+}
+
+fn main() {
+    let triangles = terrain();
+    let rays = rays();
+    println!(
+        "{} triangles, {} rays per iteration",
+        triangles.len(),
+        rays.len()
+    );
+
+    time("bvh build", 10, || {
+        Bvh::new(triangles.iter().map(|&triangle| (triangle, ())).collect())
+    });
+    let bvh = Bvh::new(triangles.iter().map(|&triangle| (triangle, ())).collect());
+
+    let linear = time("linear scan", 1, || {
+        rays.iter()
+            .map(|&ray| {
+                triangles
+                    .iter()
+                    .filter_map(|triangle| triangle.intersect(ray))
+                    .count()
+            })
+            .sum::<usize>()
+    });
+    let accelerated = time("bvh intersect", 100, || {
+        rays.iter()
+            .map(|&ray| bvh.intersect(ray, |_| true).len())
+            .sum::<usize>()
+    });
+    time("bvh nearest", 100, || {
+        rays.iter()
+            .filter_map(|&ray| bvh.nearest(ray, |_| true))
+            .count()
+    });
+
+    let linear_hits: usize = rays
+        .iter()
+        .map(|&ray| {
+            triangles
+                .iter()
+                .filter(|triangle| triangle.intersect(ray).is_some())
+                .count()
+        })
+        .sum();
+    let bvh_hits: usize = rays
+        .iter()
+        .map(|&ray| bvh.intersect(ray, |_| true).len())
+        .sum();
+    assert_eq!(linear_hits, bvh_hits, "BVH and linear scan disagree");
+    println!(
+        "speedup: {:.0}x",
+        linear.as_secs_f64() / (accelerated.as_secs_f64() / 100.)
+    );
+}
@@ -0,0 +1,435 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::HitTestSpace;
+use crate::Native;
+use crate::Ray;
+use crate::Triangle;
+
+use euclid::Point3D;
+use euclid::RigidTransform3D;
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+
+// The most triangles a leaf node may hold before it gets split
+const LEAF_SIZE: usize = 4;
+
+/// A bounding volume hierarchy over a set of triangles, each tagged with a
+/// payload identifying where it came from.
+///
+/// Building is O(n log n), and hit testing a ray is roughly O(log n) rather
+/// than testing every triangle.
+#[derive(Clone, Debug)]
+pub struct Bvh<T> {
+    nodes: Vec<Node>,
+    triangles: Vec<(Triangle, T)>,
+}
+
+/// A single intersection of a ray with a triangle in a `Bvh`
+#[derive(Clone, Copy, Debug)]
+pub struct BvhHit<'a, T> {
+    pub space: RigidTransform3D<f32, HitTestSpace, Native>,
+    /// The distance along the ray, in meters
+    pub distance: f32,
+    pub payload: &'a T,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Aabb {
+    min: Point3D<f32, Native>,
+    max: Point3D<f32, Native>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum NodeKind {
+    /// A range of `Bvh::triangles`
+    Leaf { start: usize, end: usize },
+    /// Indices of the two children in `Bvh::nodes`
+    Interior { left: usize, right: usize },
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+impl<T> Default for Bvh<T> {
+    fn default() -> Self {
+        Bvh {
+            nodes: vec![],
+            triangles: vec![],
+        }
+    }
+}
+
+impl<T> Bvh<T> {
+    pub fn new(triangles: Vec<(Triangle, T)>) -> Bvh<T> {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * triangles.len() / LEAF_SIZE + 1),
+            triangles,
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.build(0, bvh.triangles.len());
+        }
+        bvh
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    /// Every triangle the ray hits whose payload passes the filter,
+    /// sorted by distance along the ray
+    pub fn intersect(
+        &self,
+        ray: Ray<Native>,
+        mut filter: impl FnMut(&T) -> bool,
+    ) -> Vec<BvhHit<'_, T>> {
+        let mut hits = vec![];
+        self.traverse(
+            ray,
+            |_| true,
+            |triangle, payload| {
+                if filter(payload) {
+                    hits.extend(hit(ray, triangle, payload));
+                }
+            },
+        );
+        hits.sort_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(Ordering::Equal)
+        });
+        hits
+    }
+
+    /// The closest triangle the ray hits whose payload passes the filter
+    pub fn nearest(
+        &self,
+        ray: Ray<Native>,
+        mut filter: impl FnMut(&T) -> bool,
+    ) -> Option<BvhHit<'_, T>> {
+        // Nodes further away than the nearest hit so far can be skipped
+        let max = Cell::new(std::f32::INFINITY);
+        let mut nearest: Option<BvhHit<T>> = None;
+        self.traverse(
+            ray,
+            |entry| entry < max.get(),
+            |triangle, payload| {
+                if !filter(payload) {
+                    return;
+                }
+                if let Some(hit) = hit(ray, triangle, payload) {
+                    if hit.distance < max.get() {
+                        max.set(hit.distance);
+                        nearest = Some(hit);
+                    }
+                }
+            },
+        );
+        nearest
+    }
+
+    /// Visit every triangle in the leaves whose bounds the ray enters,
+    /// skipping nodes whose entry distance is rejected by `visit_node`
+    fn traverse<'a>(
+        &'a self,
+        ray: Ray<Native>,
+        mut visit_node: impl FnMut(f32) -> bool,
+        mut visit: impl FnMut(&'a Triangle, &'a T),
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inv_direction = (
+            1. / ray.direction.x,
+            1. / ray.direction.y,
+            1. / ray.direction.z,
+        );
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            match node.bounds.entry(ray, inv_direction) {
+                Some(entry) if visit_node(entry) => (),
+                _ => continue,
+            }
+            match node.kind {
+                NodeKind::Leaf { start, end } => {
+                    for (triangle, payload) in &self.triangles[start..end] {
+                        visit(triangle, payload);
+                    }
+                }
+                NodeKind::Interior { left, right } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+
+    /// Build the subtree over `triangles[start..end]`, returning its node index
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let bounds = self.triangles[start..end]
+            .iter()
+            .map(|(triangle, _)| Aabb::of_triangle(triangle))
+            .fold(Aabb::empty(), Aabb::union);
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            kind: NodeKind::Leaf { start, end },
+        });
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+
+        // Split at the median centroid along the axis with the widest spread of centroids
+        let centroids = self.triangles[start..end]
+            .iter()
+            .map(|(triangle, _)| centroid(triangle))
+            .fold(Aabb::empty(), Aabb::add_point);
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let key = |triangle: &Triangle| {
+            let centroid = centroid(triangle);
+            match axis {
+                0 => centroid.x,
+                1 => centroid.y,
+                _ => centroid.z,
+            }
+        };
+        let mid = (end - start) / 2;
+        self.triangles[start..end].select_nth_unstable_by(mid, |a, b| {
+            key(&a.0).partial_cmp(&key(&b.0)).unwrap_or(Ordering::Equal)
+        });
+
+        let left = self.build(start, start + mid);
+        let right = self.build(start + mid, end);
+        self.nodes[index].kind = NodeKind::Interior { left, right };
+        index
+    }
+}
+
+impl<T: Clone> Bvh<T> {
+    /// Build a BVH from groups of triangles, with each triangle getting its group's payload
+    pub fn from_groups<'a>(groups: impl IntoIterator<Item = (&'a [Triangle], T)>) -> Bvh<T> {
+        let triangles = groups
+            .into_iter()
+            .flat_map(|(faces, payload)| {
+                faces
+                    .iter()
+                    .map(move |triangle| (*triangle, payload.clone()))
+            })
+            .collect();
+        Bvh::new(triangles)
+    }
+}
+
+fn hit<'a, T>(ray: Ray<Native>, triangle: &Triangle, payload: &'a T) -> Option<BvhHit<'a, T>> {
+    let space = triangle.intersect(ray)?;
+    let distance = (space.translation - ray.origin).length();
+    Some(BvhHit {
+        space,
+        distance,
+        payload,
+    })
+}
+
+fn centroid(triangle: &Triangle) -> Point3D<f32, Native> {
+    ((triangle.first.to_vector() + triangle.second.to_vector() + triangle.third.to_vector()) / 3.)
+        .to_point()
+}
+
+impl Aabb {
+    fn empty() -> Aabb {
+        let inf = std::f32::INFINITY;
+        Aabb {
+            min: Point3D::new(inf, inf, inf),
+            max: Point3D::new(-inf, -inf, -inf),
+        }
+    }
+
+    fn of_triangle(triangle: &Triangle) -> Aabb {
+        Aabb::empty()
+            .add_point(triangle.first)
+            .add_point(triangle.second)
+            .add_point(triangle.third)
+    }
+
+    fn add_point(self, point: Point3D<f32, Native>) -> Aabb {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// The slab test, returning the distance along the ray at which it enters the box
+    /// https://en.wikipedia.org/wiki/Slab_method
+    fn entry(&self, ray: Ray<Native>, inv_direction: (f32, f32, f32)) -> Option<f32> {
+        let slab = |min: f32, max: f32, origin: f32, inv: f32| {
+            let t1 = (min - origin) * inv;
+            let t2 = (max - origin) * inv;
+            (t1.min(t2), t1.max(t2))
+        };
+        let (x_near, x_far) = slab(self.min.x, self.max.x, ray.origin.x, inv_direction.0);
+        let (y_near, y_far) = slab(self.min.y, self.max.y, ray.origin.y, inv_direction.1);
+        let (z_near, z_far) = slab(self.min.z, self.max.z, ray.origin.z, inv_direction.2);
+        let near = x_near.max(y_near).max(z_near).max(0.);
+        let far = x_far.min(y_far).min(z_far);
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use euclid::Vector3D;
+
+    const GRID_SIZE: usize = 8;
+    const LEVELS: usize = 3;
+
+    /// Horizontal grids of unit squares one meter apart, each triangle tagged
+    /// with its level and a unique index
+    fn floors() -> Vec<(Triangle, (usize, usize))> {
+        let mut triangles = vec![];
+        for level in 0..LEVELS {
+            let point = |x: usize, z: usize| Point3D::new(x as f32, level as f32, z as f32);
+            for x in 0..GRID_SIZE {
+                for z in 0..GRID_SIZE {
+                    let (a, b, c, d) = (
+                        point(x, z),
+                        point(x + 1, z),
+                        point(x, z + 1),
+                        point(x + 1, z + 1),
+                    );
+                    for &(first, second, third) in &[(a, c, b), (b, c, d)] {
+                        let index = triangles.len();
+                        let triangle = Triangle {
+                            first,
+                            second,
+                            third,
+                        };
+                        triangles.push((triangle, (level, index)));
+                    }
+                }
+            }
+        }
+        triangles
+    }
+
+    fn ray(origin: (f32, f32, f32), direction: (f32, f32, f32)) -> Ray<Native> {
+        Ray {
+            origin: Vector3D::new(origin.0, origin.1, origin.2),
+            direction: Vector3D::new(direction.0, direction.1, direction.2).normalize(),
+        }
+    }
+
+    /// The payloads of every triangle hit, found by testing each one
+    fn linear_scan(triangles: &[(Triangle, (usize, usize))], ray: Ray<Native>) -> Vec<usize> {
+        let mut indices: Vec<_> = triangles
+            .iter()
+            .filter(|(triangle, _)| triangle.intersect(ray).is_some())
+            .map(|&(_, (_, index))| index)
+            .collect();
+        indices.sort();
+        indices
+    }
+
+    #[test]
+    fn empty() {
+        let bvh: Bvh<()> = Bvh::new(vec![]);
+        assert!(bvh.is_empty());
+        let down = ray((0., 1., 0.), (0., -1., 0.));
+        assert!(bvh.intersect(down, |_| true).is_empty());
+        assert!(bvh.nearest(down, |_| true).is_none());
+    }
+
+    #[test]
+    fn intersect_is_sorted_by_distance() {
+        let bvh = Bvh::new(floors());
+        assert_eq!(bvh.len(), LEVELS * GRID_SIZE * GRID_SIZE * 2);
+
+        let hits = bvh.intersect(ray((2.3, 10., 4.6), (0., -1., 0.)), |_| true);
+        let levels: Vec<_> = hits.iter().map(|hit| hit.payload.0).collect();
+        assert_eq!(levels, [2, 1, 0]);
+        for (hit, expected) in hits.iter().zip(&[8., 9., 10.]) {
+            assert!((hit.distance - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn intersect_matches_linear_scan() {
+        let triangles = floors();
+        let bvh = Bvh::new(triangles.clone());
+        let rays = [
+            ray((0.37, 5., 0.61), (1., -2., 0.5)),
+            ray((7.9, 2.5, 0.1), (-3., -1., 2.)),
+            ray((4.2, -1., 3.7), (0.2, 1., -0.1)),
+            ray((-2.3, 1.5, -1.9), (1., -0.1, 1.)),
+            // Misses every level
+            ray((-5., 0.5, -5.), (0., 0., -1.)),
+        ];
+        for &ray in &rays {
+            let hits = bvh.intersect(ray, |_| true);
+            assert!(hits
+                .windows(2)
+                .all(|pair| pair[0].distance <= pair[1].distance));
+            let mut indices: Vec<_> = hits.iter().map(|hit| hit.payload.1).collect();
+            indices.sort();
+            assert_eq!(indices, linear_scan(&triangles, ray));
+        }
+    }
+
+    #[test]
+    fn nearest_respects_filter() {
+        let bvh = Bvh::new(floors());
+        let down = ray((5.5, 10., 1.2), (0., -1., 0.));
+
+        let nearest = bvh.nearest(down, |_| true).unwrap();
+        assert_eq!(nearest.payload.0, 2);
+        assert!((nearest.distance - 8.).abs() < 1e-4);
+
+        let nearest = bvh.nearest(down, |&(level, _)| level != 2).unwrap();
+        assert_eq!(nearest.payload.0, 1);
+
+        let hits = bvh.intersect(down, |&(level, _)| level == 0);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].payload.0, 0);
+
+        assert!(bvh.nearest(down, |_| false).is_none());
+    }
+
+    #[test]
+    fn from_groups_shares_payloads() {
+        let triangles: Vec<_> = floors().into_iter().map(|(triangle, _)| triangle).collect();
+        let (first, second) = triangles.split_at(10);
+        let bvh = Bvh::from_groups(vec![(first, "first"), (second, "second")]);
+        assert_eq!(bvh.len(), triangles.len());
+        let hits = bvh.intersect(ray((0.2, 10., 0.2), (0., -1., 0.)), |_| true);
+        let payloads: Vec<_> = hits.iter().map(|hit| *hit.payload).collect();
+        assert_eq!(payloads, ["second", "second", "first"]);
+    }
+}
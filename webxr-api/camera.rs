@@ -0,0 +1,63 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use euclid::Transform3D;
+
+#[cfg(feature = "ipc")]
+use serde::{Deserialize, Serialize};
+
+/// The camera image for a view, enabled with the `camera-access` feature
+/// https://immersive-web.github.io/raw-camera-access/#xrcamera-interface
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct Camera {
+    pub width: u32,
+    pub height: u32,
+    /// A texture id in the session's GL context
+    /// https://immersive-web.github.io/raw-camera-access/#dom-xrwebglbinding-getcameraimage
+    pub texture: u32,
+    pub intrinsics: CameraIntrinsics,
+}
+
+/// The pinhole camera model of a camera image, in pixels from its bottom left corner
+/// https://github.com/immersive-web/raw-camera-access/blob/main/explainer.md#computing-camera-intrinsics
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct CameraIntrinsics {
+    pub focal_length_x: f32,
+    pub focal_length_y: f32,
+    /// Where the optical axis meets the image
+    pub principal_point_x: f32,
+    pub principal_point_y: f32,
+    pub skew: f32,
+}
+
+/// A camera image in CPU memory, to be uploaded into a GL context
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct CameraImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGBA pixels, four bytes per pixel, starting at the bottom row
+    pub data: Vec<u8>,
+}
+
+impl CameraIntrinsics {
+    /// The intrinsics of a camera whose image exactly covers a view with the given projection
+    pub fn from_projection<Src, Dst>(
+        projection: &Transform3D<f32, Src, Dst>,
+        width: u32,
+        height: u32,
+    ) -> CameraIntrinsics {
+        let half_width = width as f32 / 2.;
+        let half_height = height as f32 / 2.;
+        CameraIntrinsics {
+            focal_length_x: projection.m11 * half_width,
+            focal_length_y: projection.m22 * half_height,
+            principal_point_x: (1. - projection.m31) * half_width,
+            principal_point_y: (1. - projection.m32) * half_height,
+            skew: projection.m21 * half_width,
+        }
+    }
+}
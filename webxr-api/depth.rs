@@ -0,0 +1,130 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use euclid::RigidTransform3D;
+
+#[cfg(feature = "ipc")]
+use serde::{Deserialize, Serialize};
+
+/// https://immersive-web.github.io/depth-sensing/#enumdef-xrdepthusage
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum DepthUsage {
+    CpuOptimized,
+    GpuOptimized,
+}
+
+/// https://immersive-web.github.io/depth-sensing/#enumdef-xrdepthdataformat
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum DepthDataFormat {
+    /// Two bytes per pixel, an unsigned 16 bit integer
+    LuminanceAlpha,
+    /// Four bytes per pixel, a 32 bit float
+    Float32,
+}
+
+/// https://immersive-web.github.io/depth-sensing/#dictdef-xrdepthstateinit
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct DepthSensingInit {
+    /// The usages the content can handle, most preferred first
+    pub usage_preference: Vec<DepthUsage>,
+    /// The data formats the content can handle, most preferred first
+    pub data_format_preference: Vec<DepthDataFormat>,
+}
+
+/// The depth sensing configuration a session was granted
+/// https://immersive-web.github.io/depth-sensing/#xrsession-extension
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct DepthSensing {
+    pub usage: DepthUsage,
+    pub data_format: DepthDataFormat,
+}
+
+/// Normalized view coordinates, where the view is from (0,0) at the top left to (1,1)
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum NormView {}
+
+/// Normalized depth buffer coordinates, where the buffer is from (0,0) at the top left to (1,1)
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum NormTexture {}
+
+/// https://immersive-web.github.io/depth-sensing/#xrdepthinformation
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct DepthInformation {
+    pub width: u32,
+    pub height: u32,
+    /// Multiply a raw depth value by this to get the distance in meters
+    /// along the view's -Z axis
+    pub raw_value_to_meters: f32,
+    pub norm_texture_from_norm_view: RigidTransform3D<f32, NormView, NormTexture>,
+    pub data: DepthData,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum DepthData {
+    /// Row-major native-endian values in the session's data format, starting at the top row
+    /// https://immersive-web.github.io/depth-sensing/#xrcpudepthinformation
+    Cpu(Vec<u8>),
+    /// A texture id in the session's GL context
+    /// https://immersive-web.github.io/depth-sensing/#xrwebgldepthinformation
+    Gpu(u32),
+}
+
+impl DepthSensingInit {
+    /// Pick the most preferred usage and data format the device supports.
+    /// An empty preference list means the content will accept anything.
+    pub fn select(
+        &self,
+        usages: &[DepthUsage],
+        data_formats: &[DepthDataFormat],
+    ) -> Option<DepthSensing> {
+        let usage = select(&self.usage_preference, usages)?;
+        let data_format = select(&self.data_format_preference, data_formats)?;
+        Some(DepthSensing { usage, data_format })
+    }
+}
+
+fn select<T: Copy + PartialEq>(preference: &[T], supported: &[T]) -> Option<T> {
+    if preference.is_empty() {
+        return supported.first().copied();
+    }
+    preference.iter().copied().find(|x| supported.contains(x))
+}
+
+impl DepthDataFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            DepthDataFormat::LuminanceAlpha => 2,
+            DepthDataFormat::Float32 => 4,
+        }
+    }
+
+    /// The raw value to meters conversion used for this format,
+    /// millimeters for 16 bit values and meters for floats
+    pub fn raw_value_to_meters(self) -> f32 {
+        match self {
+            DepthDataFormat::LuminanceAlpha => 0.001,
+            DepthDataFormat::Float32 => 1.,
+        }
+    }
+
+    /// Encode a depth in meters as a raw value, with zero meaning no depth is known
+    pub fn encode(self, meters: f32, buffer: &mut Vec<u8>) {
+        let raw = meters / self.raw_value_to_meters();
+        match self {
+            DepthDataFormat::LuminanceAlpha => {
+                let raw = raw.round().max(0.).min(std::u16::MAX as f32) as u16;
+                buffer.extend_from_slice(&raw.to_ne_bytes())
+            }
+            DepthDataFormat::Float32 => buffer.extend_from_slice(&raw.to_ne_bytes()),
+        }
+    }
+}
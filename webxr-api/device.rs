@@ -4,7 +4,12 @@
 
 //! Traits to be implemented by backends
 
+use crate::AnchorId;
+use crate::AnchorSpace;
+use crate::ApiSpace;
 use crate::ContextId;
+use crate::DepthSensing;
+use crate::DomOverlayType;
 use crate::EnvironmentBlendMode;
 use crate::Error;
 use crate::Event;
@@ -22,6 +27,8 @@ use crate::Session;
 use crate::SessionBuilder;
 use crate::SessionInit;
 use crate::SessionMode;
+use crate::Space;
+use crate::TransientHitTestSource;
 use crate::Viewports;
 
 use euclid::{Point2D, RigidTransform3D};
@@ -77,14 +84,61 @@ pub trait DeviceAPI: 'static {
 
     fn granted_features(&self) -> &[String];
 
+    /// The depth sensing configuration, if the `depth-sensing` feature was granted
+    fn depth_sensing(&self) -> Option<DepthSensing> {
+        None
+    }
+
     fn request_hit_test(&mut self, _source: HitTestSource) {
         panic!("This device does not support requesting hit tests");
     }
 
+    fn request_transient_hit_test(&mut self, _source: TransientHitTestSource) {
+        panic!("This device does not support requesting transient hit tests");
+    }
+
     fn cancel_hit_test(&mut self, _id: HitTestId) {
         panic!("This device does not support hit tests");
     }
 
+    fn create_anchor(
+        &mut self,
+        _space: Space,
+        _pose: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+    ) -> Result<AnchorId, Error> {
+        Err(Error::UnsupportedFeature("anchors".into()))
+    }
+
+    /// Create an anchor at a pose in native coordinates, such as a hit test result
+    fn create_native_anchor(
+        &mut self,
+        _pose: RigidTransform3D<f32, AnchorSpace, Native>,
+    ) -> Result<AnchorId, Error> {
+        Err(Error::UnsupportedFeature("anchors".into()))
+    }
+
+    /// Devices without anchor support never create any, so there is nothing to delete
+    fn delete_anchor(&mut self, _id: AnchorId) {}
+
+    /// How overlay layers are presented, if the `dom-overlay` feature was granted
+    fn dom_overlay_type(&self) -> Option<DomOverlayType> {
+        None
+    }
+
+    /// Start providing light estimates in each frame
+    fn request_light_probe(&mut self) -> Result<(), Error> {
+        Err(Error::UnsupportedFeature("light-estimation".into()))
+    }
+
+    /// Set the amount of foveation of a layer, returning the amount actually applied,
+    /// which is 0 for devices that don't support foveation
+    fn set_layer_foveation(&mut self, _layer_id: LayerId, _level: f32) -> f32 {
+        0.0
+    }
+
+    /// Shrink the viewports of projection layers' views, starting with the next frame
+    fn request_viewport_scale(&mut self, _scale: f32) {}
+
     fn update_frame_rate(&mut self, rate: f32) -> f32 {
         rate
     }
@@ -2,12 +2,17 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::AnchorPose;
+use crate::DetectedPlane;
 use crate::Floor;
 use crate::HitTestId;
 use crate::HitTestResult;
 use crate::InputFrame;
+use crate::LightEstimate;
+use crate::MeshUpdate;
 use crate::Native;
 use crate::SubImages;
+use crate::TransientHitTestResult;
 use crate::Viewer;
 use crate::Viewports;
 use crate::Views;
@@ -34,7 +39,24 @@ pub struct Frame {
     /// The hit test results for this frame, if any
     pub hit_test_results: Vec<HitTestResult>,
 
-    /// The average point in time this XRFrame is expected to be displayed on the devices' display
+    /// The hit test results for transient input sources, grouped by input source
+    pub transient_hit_test_results: Vec<TransientHitTestResult>,
+
+    /// The poses of the anchors created in this session
+    pub anchor_poses: Vec<AnchorPose>,
+
+    /// The planes detected in the real world, if plane detection is enabled
+    pub detected_planes: Vec<DetectedPlane>,
+
+    /// The changes to the meshes detected in the real world since the last frame,
+    /// if mesh detection is enabled
+    pub mesh_updates: Vec<MeshUpdate>,
+
+    /// The estimated lighting conditions, if a light probe has been requested
+    pub light_estimate: Option<LightEstimate>,
+
+    /// The average point in time this XRFrame is expected to be displayed on the devices' display,
+    /// in nanoseconds. Later frames of a session have later times.
     pub predicted_display_time: f64,
 }
 
@@ -44,6 +66,8 @@ pub enum FrameUpdateEvent {
     UpdateFloorTransform(Option<RigidTransform3D<f32, Native, Floor>>),
     UpdateViewports(Viewports),
     HitTestSourceAdded(HitTestId),
+    /// The frame rate of the device, in frames per second
+    UpdateFrameRate(f32),
 }
 
 #[derive(Clone, Debug)]
@@ -1,4 +1,5 @@
 use crate::ApiSpace;
+use crate::InputId;
 use crate::Native;
 use crate::Space;
 use euclid::Point3D;
@@ -18,7 +19,7 @@ pub struct Ray<Space> {
     pub direction: Vector3D<f32, Space>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 /// https://immersive-web.github.io/hit-test/#enumdef-xrhittesttrackabletype
 pub enum EntityType {
@@ -37,6 +38,18 @@ pub struct HitTestSource {
     pub types: EntityTypes,
 }
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/hit-test/#dictdef-xrtransientinputhittestoptionsinit
+pub struct TransientHitTestSource {
+    pub id: HitTestId,
+    /// The profile that transient input sources must have to be hit tested
+    pub profile: String,
+    /// The ray, in the target ray space of each matching input source
+    pub ray: Ray<ApiSpace>,
+    pub types: EntityTypes,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct HitTestId(pub u32);
@@ -52,9 +65,27 @@ pub struct EntityTypes {
 
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/hit-test/#xrhittestresult-interface
 pub struct HitTestResult {
     pub id: HitTestId,
     pub space: RigidTransform3D<f32, HitTestSpace, Native>,
+    /// The distance from the ray origin to the hit, in meters
+    pub distance: f32,
+    /// The type of entity that was hit
+    pub entity_type: EntityType,
+    /// The identifier of the entity that was hit, if it has one.
+    /// For planes and meshes this is the same as their `PlaneId` or `MeshId`.
+    pub entity_id: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/hit-test/#xrtransientinputhittestresult-interface
+pub struct TransientHitTestResult {
+    pub id: HitTestId,
+    /// The transient input source whose target ray was hit tested
+    pub input: InputId,
+    pub results: Vec<HitTestResult>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -2,11 +2,18 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::cube_face_rects;
+use crate::ApiSpace;
+use crate::CameraImage;
 use crate::Error;
+use crate::Space;
 use crate::Viewport;
 use crate::Viewports;
 
+use euclid::Point2D;
 use euclid::Rect;
+use euclid::RigidTransform3D;
+use euclid::Rotation3D;
 use euclid::Size2D;
 
 use std::fmt::Debug;
@@ -118,6 +125,36 @@ pub trait LayerManagerAPI<GL: GLTypes> {
         contexts: &mut dyn GLContexts<GL>,
         layers: &[(ContextId, LayerId)],
     ) -> Result<(), Error>;
+
+    /// Upload a camera image into a texture in the given context, returning the texture id.
+    /// The texture is reused by later uploads to the same context.
+    fn upload_camera_image(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _context_id: ContextId,
+        _image: &CameraImage,
+    ) -> Result<u32, Error> {
+        Err(Error::UnsupportedFeature("camera-access".into()))
+    }
+
+    /// Read back the color texture most recently submitted for a layer
+    fn read_pixels(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _context_id: ContextId,
+        _layer_id: LayerId,
+    ) -> Result<LayerImage, Error> {
+        Err(Error::UnsupportedFeature("read-pixels".into()))
+    }
+
+    /// Shrink the viewports of projection layers' view sub images, starting with the next frame.
+    /// The layers' textures are not reallocated.
+    fn set_viewport_scale(&mut self, _scale: f32) {}
+
+    /// Whether `end_frame` reads back submitted color textures for `read_pixels`
+    fn set_read_back(&mut self, _read_back: bool) {}
 }
 
 pub struct LayerManager(Box<dyn Send + LayerManagerAPI<()>>);
@@ -151,6 +188,31 @@ impl LayerManager {
     pub fn end_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Result<(), Error> {
         self.0.end_frame(&mut (), &mut (), layers)
     }
+
+    pub fn upload_camera_image(
+        &mut self,
+        context_id: ContextId,
+        image: &CameraImage,
+    ) -> Result<u32, Error> {
+        self.0
+            .upload_camera_image(&mut (), &mut (), context_id, image)
+    }
+
+    pub fn read_pixels(
+        &mut self,
+        context_id: ContextId,
+        layer_id: LayerId,
+    ) -> Result<LayerImage, Error> {
+        self.0.read_pixels(&mut (), &mut (), context_id, layer_id)
+    }
+
+    pub fn set_viewport_scale(&mut self, scale: f32) {
+        self.0.set_viewport_scale(scale)
+    }
+
+    pub fn set_read_back(&mut self, read_back: bool) {
+        self.0.set_read_back(read_back)
+    }
 }
 
 impl LayerManager {
@@ -228,8 +290,12 @@ pub enum LayerInit {
         depth: bool,
         stencil: bool,
         alpha: bool,
+        /// If false, the layer's depth is submitted to the device's compositor
         ignore_depth_values: bool,
         framebuffer_scale_factor: f32,
+        /// The amount of foveation, from 0 (none) to 1 (maximum)
+        /// https://immersive-web.github.io/layers/#dom-xrcompositionlayer-fixedfoveation
+        fixed_foveation: f32,
     },
     // https://immersive-web.github.io/layers/#xrprojectionlayerinittype
     ProjectionLayer {
@@ -237,8 +303,82 @@ pub enum LayerInit {
         stencil: bool,
         alpha: bool,
         scale_factor: f32,
+        /// How the views are laid out in the texture, with `Default`
+        /// using the device's viewports
+        layout: LayerLayout,
+        texture_type: TextureType,
+        /// The depth/stencil format, overriding the one implied by `depth` and `stencil`,
+        /// for example to get floating point depth
+        /// https://immersive-web.github.io/layers/#dom-xrprojectionlayerinit-depthformat
+        depth_format: Option<DepthStencilFormat>,
+        /// The amount of foveation, from 0 (none) to 1 (maximum)
+        /// https://immersive-web.github.io/layers/#dom-xrcompositionlayer-fixedfoveation
+        fixed_foveation: f32,
+    },
+    /// A 2D layer composited on top of all other layers, for example to show
+    /// DOM content over an immersive session
+    /// https://immersive-web.github.io/dom-overlays/
+    Overlay {
+        size: Size2D<i32, Viewport>,
+        alpha: bool,
+    },
+    // https://immersive-web.github.io/layers/#dictdef-xrquadlayerinit
+    QuadLayer {
+        space: Space,
+        /// The pose of the center of the quad, relative to `space`
+        transform: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+        /// The width of the quad, in meters
+        width: f32,
+        /// The height of the quad, in meters
+        height: f32,
+        layout: LayerLayout,
+        /// The size of the sub image for each view
+        texture_size: Size2D<i32, Viewport>,
+    },
+    // https://immersive-web.github.io/layers/#dictdef-xrcylinderlayerinit
+    CylinderLayer {
+        space: Space,
+        /// The pose of the center of the cylinder, relative to `space`.
+        /// The cylinder's axis is the Y axis, and its visible arc is centered on -Z.
+        transform: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+        /// The radius of the cylinder, in meters
+        radius: f32,
+        /// The angle of the visible arc, in radians
+        central_angle: f32,
+        /// The ratio of the visible arc's length to its height
+        aspect_ratio: f32,
+        layout: LayerLayout,
+        /// The size of the sub image for each view
+        texture_size: Size2D<i32, Viewport>,
+    },
+    // https://immersive-web.github.io/layers/#dictdef-xrequirectlayerinit
+    EquirectLayer {
+        space: Space,
+        /// The pose of the center of the sphere, relative to `space`.
+        /// The center of the image is in the -Z direction.
+        transform: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+        /// The radius of the sphere, in meters, with zero meaning an infinite sphere
+        radius: f32,
+        /// The angle of longitude the image covers, in radians
+        central_horizontal_angle: f32,
+        /// The latitude of the top of the image, in radians
+        upper_vertical_angle: f32,
+        /// The latitude of the bottom of the image, in radians
+        lower_vertical_angle: f32,
+        layout: LayerLayout,
+        /// The size of the sub image for each view
+        texture_size: Size2D<i32, Viewport>,
+    },
+    // https://immersive-web.github.io/layers/#dictdef-xrcubelayerinit
+    CubeLayer {
+        space: Space,
+        /// The orientation of the cube, relative to `space`
+        orientation: Rotation3D<f32, ApiSpace, ApiSpace>,
+        layout: LayerLayout,
+        /// The size of each face of the cube. The faces are laid out in a
+        /// 3x2 grid in each sub image, in the same order as cubemap views.
+        texture_size: Size2D<i32, Viewport>,
     },
-    // TODO: other layer types
 }
 
 impl LayerInit {
@@ -250,6 +390,12 @@ impl LayerInit {
             }
             | LayerInit::ProjectionLayer {
                 scale_factor: scale,
+                layout: LayerLayout::Default,
+                ..
+            }
+            | LayerInit::ProjectionLayer {
+                scale_factor: scale,
+                texture_type: TextureType::TextureArray,
                 ..
             } => {
                 let native_size = viewports
@@ -259,15 +405,279 @@ impl LayerInit {
                     .size;
                 (native_size.to_f32() * *scale).to_i32()
             }
+            LayerInit::ProjectionLayer {
+                scale_factor,
+                layout,
+                ..
+            } => layout.texture_size(projection_view_size(viewports, *scale_factor)),
+            LayerInit::Overlay { size, .. } => *size,
+            LayerInit::QuadLayer {
+                layout,
+                texture_size,
+                ..
+            }
+            | LayerInit::CylinderLayer {
+                layout,
+                texture_size,
+                ..
+            }
+            | LayerInit::EquirectLayer {
+                layout,
+                texture_size,
+                ..
+            } => layout.texture_size(*texture_size),
+            LayerInit::CubeLayer {
+                layout,
+                texture_size,
+                ..
+            } => layout.texture_size(cube_grid_size(*texture_size)),
+        }
+    }
+
+    /// The format of the depth/stencil texture to allocate, if any
+    pub fn depth_stencil_format(&self) -> Option<DepthStencilFormat> {
+        match *self {
+            LayerInit::ProjectionLayer {
+                depth_format: Some(format),
+                ..
+            } => Some(format),
+            LayerInit::WebGLLayer { depth, stencil, .. }
+            | LayerInit::ProjectionLayer { depth, stencil, .. } => {
+                DepthStencilFormat::from_flags(depth, stencil)
+            }
+            LayerInit::Overlay { .. }
+            | LayerInit::QuadLayer { .. }
+            | LayerInit::CylinderLayer { .. }
+            | LayerInit::EquirectLayer { .. }
+            | LayerInit::CubeLayer { .. } => None,
+        }
+    }
+
+    /// Whether content renders to multisampled buffers, which are resolved at the end of each frame
+    pub fn antialias(&self) -> bool {
+        match *self {
+            LayerInit::WebGLLayer { antialias, .. } => antialias,
+            _ => false,
+        }
+    }
+
+    /// Whether the layer's depth values are submitted to the device's compositor,
+    /// for example for depth-aware compositing or reprojection
+    /// https://immersive-web.github.io/layers/#dom-xrcompositionlayer-ignoredepthvalues
+    pub fn submits_depth(&self) -> bool {
+        match *self {
+            LayerInit::WebGLLayer {
+                depth,
+                ignore_depth_values,
+                ..
+            } => depth && !ignore_depth_values,
+            // TODO: submit depth from texture arrays
+            LayerInit::ProjectionLayer {
+                texture_type: TextureType::Texture,
+                ..
+            } => self
+                .depth_stencil_format()
+                .map_or(false, DepthStencilFormat::has_depth),
+            _ => false,
+        }
+    }
+
+    /// For layers backed by a texture array, the size of each slice and the number of slices.
+    /// Each view renders to the slice with its index, and `texture_size` is then the size
+    /// of a texture with the views laid out as the device's viewports.
+    pub fn texture_array(&self, viewports: &Viewports) -> Option<(Size2D<i32, Viewport>, usize)> {
+        match *self {
+            LayerInit::ProjectionLayer {
+                scale_factor,
+                texture_type: TextureType::TextureArray,
+                ..
+            } => Some((
+                projection_view_size(viewports, scale_factor),
+                viewports.viewports.len(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// The requested amount of foveation, which is 0 for layers that can't be foveated
+    pub fn fixed_foveation(&self) -> f32 {
+        match *self {
+            LayerInit::WebGLLayer {
+                fixed_foveation, ..
+            }
+            | LayerInit::ProjectionLayer {
+                fixed_foveation, ..
+            } => fixed_foveation,
+            _ => 0.0,
+        }
+    }
+
+    /// Whether the layer is drawn per view, so its views' viewports can be scaled
+    pub fn is_projection(&self) -> bool {
+        matches!(
+            self,
+            LayerInit::WebGLLayer { .. } | LayerInit::ProjectionLayer { .. }
+        )
+    }
+
+    /// The space and pose of layers positioned in the world rather than drawn per view
+    pub fn world_pose(&self) -> Option<(Space, RigidTransform3D<f32, ApiSpace, ApiSpace>)> {
+        match *self {
+            LayerInit::QuadLayer {
+                space, transform, ..
+            }
+            | LayerInit::CylinderLayer {
+                space, transform, ..
+            }
+            | LayerInit::EquirectLayer {
+                space, transform, ..
+            } => Some((space, transform)),
+            LayerInit::CubeLayer {
+                space, orientation, ..
+            } => Some((space, RigidTransform3D::from_rotation(orientation))),
+            LayerInit::WebGLLayer { .. }
+            | LayerInit::ProjectionLayer { .. }
+            | LayerInit::Overlay { .. } => None,
+        }
+    }
+
+    /// The region of the texture to use for each view, in the order of fields in Views.
+    /// This is empty for layers that are not composited per view.
+    pub fn view_sub_image_rects(&self, viewports: &Viewports) -> Vec<Rect<i32, Viewport>> {
+        match self {
+            LayerInit::ProjectionLayer {
+                scale_factor,
+                texture_type: TextureType::TextureArray,
+                ..
+            } => {
+                let size = projection_view_size(viewports, *scale_factor);
+                vec![Rect::new(Point2D::zero(), size); viewports.viewports.len()]
+            }
+            LayerInit::WebGLLayer { .. }
+            | LayerInit::ProjectionLayer {
+                layout: LayerLayout::Default,
+                ..
+            } => viewports.viewports.clone(),
+            LayerInit::ProjectionLayer {
+                scale_factor,
+                layout,
+                ..
+            } => layout.sub_image_rects(
+                projection_view_size(viewports, *scale_factor),
+                viewports.viewports.len(),
+            ),
+            LayerInit::Overlay { .. } => vec![],
+            LayerInit::QuadLayer {
+                layout,
+                texture_size,
+                ..
+            }
+            | LayerInit::CylinderLayer {
+                layout,
+                texture_size,
+                ..
+            }
+            | LayerInit::EquirectLayer {
+                layout,
+                texture_size,
+                ..
+            } => layout.sub_image_rects(*texture_size, viewports.viewports.len()),
+            LayerInit::CubeLayer {
+                layout,
+                texture_size,
+                ..
+            } => layout.sub_image_rects(cube_grid_size(*texture_size), viewports.viewports.len()),
         }
     }
 }
 
+/// The size of each view's sub image in a projection layer with an explicit layout,
+/// big enough for the largest viewport
+fn projection_view_size(viewports: &Viewports, scale: f32) -> Size2D<i32, Viewport> {
+    let size = viewports
+        .viewports
+        .iter()
+        .fold(Size2D::zero(), |acc: Size2D<i32, Viewport>, view| {
+            acc.max(view.size)
+        });
+    (size.to_f32() * scale).to_i32()
+}
+
+/// The size of the grid of cube faces laid out by `cube_face_rects`
+fn cube_grid_size(face_size: Size2D<i32, Viewport>) -> Size2D<i32, Viewport> {
+    cube_face_rects(face_size)
+        .iter()
+        .fold(Rect::zero(), |acc, face| acc.union(face))
+        .size
+}
+
+/// https://immersive-web.github.io/dom-overlays/#enumdef-xrdomoverlaytype
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum DomOverlayType {
+    Screen,
+    Floating,
+    HeadLocked,
+}
+
+/// The format of a layer's depth and/or stencil texture
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum DepthStencilFormat {
+    // DEPTH_COMPONENT24
+    Depth24,
+    // DEPTH_COMPONENT32F
+    Depth32F,
+    // STENCIL_INDEX8
+    Stencil8,
+    // DEPTH24_STENCIL8
+    Depth24Stencil8,
+    // DEPTH32F_STENCIL8
+    Depth32FStencil8,
+}
+
+impl DepthStencilFormat {
+    /// The smallest format with the requested buffers
+    pub fn from_flags(depth: bool, stencil: bool) -> Option<DepthStencilFormat> {
+        match (depth, stencil) {
+            (true, true) => Some(DepthStencilFormat::Depth24Stencil8),
+            (true, false) => Some(DepthStencilFormat::Depth24),
+            (false, true) => Some(DepthStencilFormat::Stencil8),
+            (false, false) => None,
+        }
+    }
+
+    pub fn has_depth(self) -> bool {
+        self != DepthStencilFormat::Stencil8
+    }
+
+    pub fn has_stencil(self) -> bool {
+        match self {
+            DepthStencilFormat::Stencil8
+            | DepthStencilFormat::Depth24Stencil8
+            | DepthStencilFormat::Depth32FStencil8 => true,
+            DepthStencilFormat::Depth24 | DepthStencilFormat::Depth32F => false,
+        }
+    }
+}
+
+/// https://immersive-web.github.io/layers/#enumdef-xrtexturetype
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum TextureType {
+    // A GL_TEXTURE_2D, with the views laid out according to the layer's layout
+    Texture,
+    // A GL_TEXTURE_2D_ARRAY, with one slice per view, for example for multiview rendering
+    TextureArray,
+}
+
 /// https://immersive-web.github.io/layers/#enumdef-xrlayerlayout
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
 pub enum LayerLayout {
-    // TODO: Default
+    // The layout chosen by the device, which is only valid for projection layers.
+    // Other layers treat it as Mono.
+    Default,
     // Allocates one texture
     Mono,
     // Allocates one texture, which is split in half vertically, giving two subimages
@@ -276,6 +686,44 @@ pub enum LayerLayout {
     StereoTopBottom,
 }
 
+impl LayerLayout {
+    /// The size of a texture holding sub images of the given size
+    fn texture_size(self, sub_image_size: Size2D<i32, Viewport>) -> Size2D<i32, Viewport> {
+        match self {
+            LayerLayout::Default | LayerLayout::Mono => sub_image_size,
+            LayerLayout::StereoLeftRight => {
+                Size2D::new(sub_image_size.width * 2, sub_image_size.height)
+            }
+            LayerLayout::StereoTopBottom => {
+                Size2D::new(sub_image_size.width, sub_image_size.height * 2)
+            }
+        }
+    }
+
+    /// The sub image for each of `view_count` views
+    fn sub_image_rects(
+        self,
+        size: Size2D<i32, Viewport>,
+        view_count: usize,
+    ) -> Vec<Rect<i32, Viewport>> {
+        let rects = match self {
+            LayerLayout::Default | LayerLayout::Mono => [Rect::new(Point2D::zero(), size); 2],
+            LayerLayout::StereoLeftRight => [
+                Rect::new(Point2D::zero(), size),
+                Rect::new(Point2D::new(size.width, 0), size),
+            ],
+            LayerLayout::StereoTopBottom => [
+                Rect::new(Point2D::zero(), size),
+                Rect::new(Point2D::new(0, size.height), size),
+            ],
+        };
+        // Any views beyond the two eyes see the left eye's image
+        (0..view_count)
+            .map(|i| rects[if i == 1 { 1 } else { 0 }])
+            .collect()
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
 pub struct SubImages {
@@ -291,6 +739,91 @@ pub struct SubImage {
     pub color_texture: u32,
     // TODO: make this Option<NonZeroU32>
     pub depth_stencil_texture: Option<u32>,
+    pub depth_stencil_format: Option<DepthStencilFormat>,
     pub texture_array_index: Option<u32>,
     pub viewport: Rect<i32, Viewport>,
+    /// For antialiased layers, a multisampled framebuffer to render to rather than
+    /// the textures, which is resolved into them at the end of the frame
+    pub multisample_framebuffer: Option<u32>,
+}
+
+/// The pixels of a layer's color texture, read back after it was submitted
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct LayerImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGBA pixels, four bytes per pixel, starting at the bottom row
+    pub data: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect<i32, Viewport> {
+        Rect::new(Point2D::new(x, y), Size2D::new(width, height))
+    }
+
+    #[test]
+    fn mono_layouts_share_one_image() {
+        let size = Size2D::new(100, 50);
+        for &layout in &[LayerLayout::Default, LayerLayout::Mono] {
+            assert_eq!(layout.sub_image_rects(size, 1), [rect(0, 0, 100, 50)]);
+            assert_eq!(
+                layout.sub_image_rects(size, 2),
+                [rect(0, 0, 100, 50), rect(0, 0, 100, 50)]
+            );
+            assert_eq!(layout.texture_size(size), size);
+        }
+    }
+
+    #[test]
+    fn stereo_layouts_split_the_texture() {
+        let size = Size2D::new(100, 50);
+        assert_eq!(
+            LayerLayout::StereoLeftRight.sub_image_rects(size, 2),
+            [rect(0, 0, 100, 50), rect(100, 0, 100, 50)]
+        );
+        assert_eq!(
+            LayerLayout::StereoLeftRight.texture_size(size),
+            Size2D::new(200, 50)
+        );
+        assert_eq!(
+            LayerLayout::StereoTopBottom.sub_image_rects(size, 2),
+            [rect(0, 0, 100, 50), rect(0, 50, 100, 50)]
+        );
+        assert_eq!(
+            LayerLayout::StereoTopBottom.texture_size(size),
+            Size2D::new(100, 100)
+        );
+    }
+
+    #[test]
+    fn sub_images_fit_in_the_texture() {
+        let size = Size2D::new(64, 48);
+        let layouts = [
+            LayerLayout::Default,
+            LayerLayout::Mono,
+            LayerLayout::StereoLeftRight,
+            LayerLayout::StereoTopBottom,
+        ];
+        for &layout in &layouts {
+            let texture = Rect::from_size(layout.texture_size(size));
+            for sub_image in layout.sub_image_rects(size, 2) {
+                assert!(texture.contains_rect(&sub_image), "{:?}", layout);
+            }
+        }
+    }
+
+    #[test]
+    fn extra_views_see_the_left_eye() {
+        let size = Size2D::new(100, 50);
+        let rects = LayerLayout::StereoLeftRight.sub_image_rects(size, 3);
+        assert_eq!(rects.len(), 3);
+        assert_eq!(rects[2], rects[0]);
+        assert!(LayerLayout::StereoTopBottom
+            .sub_image_rects(size, 0)
+            .is_empty());
+    }
 }
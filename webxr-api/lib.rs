@@ -4,6 +4,10 @@
 
 //! This crate defines the Rust API for WebXR. It is implemented by the `webxr` crate.
 
+mod anchor;
+mod bvh;
+mod camera;
+mod depth;
 mod device;
 mod error;
 mod events;
@@ -12,13 +16,36 @@ mod hand;
 mod hittest;
 mod input;
 mod layer;
+mod light;
+mod mesh;
 mod mock;
+mod plane;
 mod registry;
 mod session;
 mod space;
 pub mod util;
 mod view;
 
+pub use anchor::AnchorId;
+pub use anchor::AnchorPose;
+pub use anchor::AnchorSpace;
+
+pub use bvh::Bvh;
+pub use bvh::BvhHit;
+
+pub use camera::Camera;
+pub use camera::CameraImage;
+pub use camera::CameraIntrinsics;
+
+pub use depth::DepthData;
+pub use depth::DepthDataFormat;
+pub use depth::DepthInformation;
+pub use depth::DepthSensing;
+pub use depth::DepthSensingInit;
+pub use depth::DepthUsage;
+pub use depth::NormTexture;
+pub use depth::NormView;
+
 pub use device::DeviceAPI;
 pub use device::DiscoveryAPI;
 
@@ -46,6 +73,8 @@ pub use hittest::HitTestResult;
 pub use hittest::HitTestSource;
 pub use hittest::HitTestSpace;
 pub use hittest::Ray;
+pub use hittest::TransientHitTestResult;
+pub use hittest::TransientHitTestSource;
 pub use hittest::Triangle;
 
 pub use input::Handedness;
@@ -57,11 +86,14 @@ pub use input::SelectKind;
 pub use input::TargetRayMode;
 
 pub use layer::ContextId;
+pub use layer::DepthStencilFormat;
+pub use layer::DomOverlayType;
 pub use layer::GLContexts;
 pub use layer::GLTypes;
 pub use layer::LayerGrandManager;
 pub use layer::LayerGrandManagerAPI;
 pub use layer::LayerId;
+pub use layer::LayerImage;
 pub use layer::LayerInit;
 pub use layer::LayerLayout;
 pub use layer::LayerManager;
@@ -69,6 +101,14 @@ pub use layer::LayerManagerAPI;
 pub use layer::LayerManagerFactory;
 pub use layer::SubImage;
 pub use layer::SubImages;
+pub use layer::TextureType;
+
+pub use mesh::DetectedMesh;
+pub use mesh::MeshId;
+pub use mesh::MeshSpace;
+pub use mesh::MeshUpdate;
+
+pub use light::LightEstimate;
 
 pub use mock::MockButton;
 pub use mock::MockButtonType;
@@ -82,6 +122,11 @@ pub use mock::MockViewInit;
 pub use mock::MockViewsInit;
 pub use mock::MockWorld;
 
+pub use plane::DetectedPlane;
+pub use plane::PlaneId;
+pub use plane::PlaneOrientation;
+pub use plane::PlaneSpace;
+
 pub use registry::MainThreadRegistry;
 pub use registry::MainThreadWaker;
 pub use registry::Registry;
@@ -95,11 +140,14 @@ pub use session::SessionId;
 pub use session::SessionInit;
 pub use session::SessionMode;
 pub use session::SessionThread;
+pub use session::MIN_VIEWPORT_SCALE;
 
 pub use space::ApiSpace;
 pub use space::BaseSpace;
 pub use space::Space;
 
+pub use view::cube_face_rects;
+pub use view::scale_viewport;
 pub use view::Capture;
 pub use view::CubeBack;
 pub use view::CubeBottom;
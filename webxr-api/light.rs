@@ -0,0 +1,24 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Native;
+
+use euclid::Vector3D;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/lighting-estimation/#xrlightestimate
+pub struct LightEstimate {
+    /// Nine RGB spherical harmonics coefficients of the ambient irradiance,
+    /// in the order given by
+    /// https://immersive-web.github.io/lighting-estimation/#dom-xrlightestimate-sphericalharmonicscoefficients
+    pub spherical_harmonics_coefficients: [f32; 27],
+    /// The unit direction towards the primary light source, in native coordinates
+    pub primary_light_direction: Vector3D<f32, Native>,
+    /// The RGB intensity of the primary light source
+    pub primary_light_intensity: [f32; 3],
+    /// A cube map texture id in the session's GL context, if the device provides reflections
+    /// https://immersive-web.github.io/lighting-estimation/#dom-xrwebglbinding-getreflectioncubemap
+    pub reflection_cube_map: Option<u32>,
+}
@@ -0,0 +1,87 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Native;
+use crate::Triangle;
+
+use euclid::Point3D;
+use euclid::RigidTransform3D;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeshId(pub u32);
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The coordinate space of a detected mesh
+pub struct MeshSpace;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/real-world-meshing/#xrmesh
+pub struct DetectedMesh {
+    pub id: MeshId,
+    pub pose: RigidTransform3D<f32, MeshSpace, Native>,
+    pub vertices: Vec<Point3D<f32, MeshSpace>>,
+    /// Indices into `vertices`, three per triangle
+    pub indices: Vec<u32>,
+    pub semantic_label: Option<String>,
+    /// When the mesh's geometry last changed, in milliseconds
+    pub last_changed_time: f64,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// A change to the set of detected meshes since the previous frame
+pub enum MeshUpdate {
+    Added(DetectedMesh),
+    Updated(DetectedMesh),
+    Removed(MeshId),
+}
+
+impl DetectedMesh {
+    /// Build a mesh from triangles in native coordinates
+    pub fn from_triangles(
+        id: MeshId,
+        faces: &[Triangle],
+        semantic_label: Option<String>,
+        last_changed_time: f64,
+    ) -> DetectedMesh {
+        let vertices = faces
+            .iter()
+            .flat_map(|face| vec![face.first, face.second, face.third])
+            .map(|vertex| vertex.cast_unit())
+            .collect();
+        let indices = (0..faces.len() as u32 * 3).collect();
+        DetectedMesh {
+            id,
+            pose: RigidTransform3D::identity(),
+            vertices,
+            indices,
+            semantic_label,
+            last_changed_time,
+        }
+    }
+
+    /// Whether two meshes have the same pose and triangles
+    pub fn same_geometry(&self, other: &DetectedMesh) -> bool {
+        self.pose.rotation == other.pose.rotation
+            && self.pose.translation == other.pose.translation
+            && self.vertices == other.vertices
+            && self.indices == other.indices
+    }
+
+    /// The triangles of this mesh, in native coordinates
+    pub fn triangles(&self) -> impl Iterator<Item = Triangle> + '_ {
+        let vertex = move |index: u32| {
+            let vertex = self.vertices[index as usize];
+            self.pose.rotation.transform_point3d(vertex) + self.pose.translation
+        };
+        self.indices.chunks_exact(3).map(move |face| Triangle {
+            first: vertex(face[0]),
+            second: vertex(face[1]),
+            third: vertex(face[2]),
+        })
+    }
+}
@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::CameraImage;
 use crate::DiscoveryAPI;
 use crate::Display;
 use crate::EntityType;
@@ -11,7 +12,10 @@ use crate::Handedness;
 use crate::Input;
 use crate::InputId;
 use crate::InputSource;
+use crate::LayerId;
+use crate::LayerImage;
 use crate::LeftEye;
+use crate::LightEstimate;
 use crate::Native;
 use crate::Receiver;
 use crate::RightEye;
@@ -26,6 +30,8 @@ use crate::Visibility;
 
 use euclid::{Point2D, Rect, RigidTransform3D, Transform3D};
 
+use std::time::Duration;
+
 #[cfg(feature = "ipc")]
 use serde::{Deserialize, Serialize};
 
@@ -49,6 +55,13 @@ pub struct MockDeviceInit {
     pub views: MockViewsInit,
     pub supported_features: Vec<String>,
     pub world: Option<MockWorld>,
+    /// If true, time passes by one frame at the frame rate after each frame and when
+    /// advanced by `MockDeviceMsg::AdvanceTime`, and frames are produced as fast as
+    /// they are requested
+    pub virtual_clock: bool,
+    /// The frame rates that sessions can choose between, in frames per second.
+    /// The highest is used by default, and rates that aren't positive are ignored.
+    pub supported_frame_rates: Vec<f32>,
 }
 
 #[derive(Clone, Debug)]
@@ -82,6 +95,15 @@ pub enum MockDeviceMsg {
     Disconnect(Sender<()>),
     SetBoundsGeometry(Vec<Point2D<f32, Floor>>),
     SimulateResetPose,
+    SetLightEstimate(Option<LightEstimate>),
+    SetCameraImage(Option<CameraImage>),
+    /// Get the amount of foveation applied to a layer, if it exists
+    GetLayerFoveation(LayerId, Sender<Option<f32>>),
+    /// Get the color textures submitted for each layer in the next frame.
+    /// Textures are only read back for frames that are captured or recorded.
+    CaptureFrame(Sender<Vec<(LayerId, LayerImage)>>),
+    /// Advance a virtual clock, which is ignored by devices that run in real time
+    AdvanceTime(Duration),
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,170 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Native;
+use crate::Triangle;
+
+use euclid::Point3D;
+use euclid::RigidTransform3D;
+use euclid::Rotation3D;
+use euclid::Vector3D;
+
+use std::f32::EPSILON;
+
+// How close to the up axis does a normal have to be for the plane to be horizontal?
+const HORIZONTAL_THRESHOLD: f32 = 0.9;
+
+// How close to the horizon does a normal have to be for the plane to be vertical?
+const VERTICAL_THRESHOLD: f32 = 0.1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlaneId(pub u32);
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The coordinate space of a detected plane, where the Y axis is the plane normal
+pub struct PlaneSpace;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/real-world-geometry/plane-detection.html#enumdef-xrplaneorientation
+pub enum PlaneOrientation {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/real-world-geometry/plane-detection.html#xrplane
+pub struct DetectedPlane {
+    pub id: PlaneId,
+    /// The pose of the plane's center in native coordinates
+    pub pose: RigidTransform3D<f32, PlaneSpace, Native>,
+    /// The convex outline of the plane, with every point having a Y coordinate of zero
+    pub polygon: Vec<Point3D<f32, PlaneSpace>>,
+    /// None if the plane is neither horizontal nor vertical
+    pub orientation: Option<PlaneOrientation>,
+    /// When the plane's geometry last changed, in milliseconds
+    pub last_changed_time: f64,
+}
+
+impl DetectedPlane {
+    /// Fit a plane to a set of coplanar triangles, returning None if they are all degenerate
+    pub fn from_triangles(
+        id: PlaneId,
+        faces: &[Triangle],
+        last_changed_time: f64,
+    ) -> Option<DetectedPlane> {
+        let normal = faces
+            .iter()
+            .map(|face| (face.second - face.first).cross(face.third - face.first))
+            .fold(Vector3D::zero(), |acc, normal| acc + normal);
+        if normal.length() < EPSILON {
+            return None;
+        }
+        let normal = normal.normalize();
+
+        let vertices: Vec<Point3D<f32, Native>> = faces
+            .iter()
+            .flat_map(|face| vec![face.first, face.second, face.third])
+            .collect();
+        let center = vertices
+            .iter()
+            .fold(Vector3D::zero(), |acc, vertex| acc + vertex.to_vector())
+            / vertices.len() as f32;
+
+        let rotation = rotation_from_normal(normal);
+        let native_to_plane = rotation.inverse();
+        let projected = vertices
+            .iter()
+            .map(|vertex| native_to_plane.transform_vector3d(vertex.to_vector() - center))
+            .map(|point| Point3D::new(point.x, 0., point.z))
+            .collect();
+        let polygon = convex_hull(projected);
+        let pose = RigidTransform3D::new(rotation, center);
+
+        let up = normal.y.abs();
+        let orientation = if up > HORIZONTAL_THRESHOLD {
+            Some(PlaneOrientation::Horizontal)
+        } else if up < VERTICAL_THRESHOLD {
+            Some(PlaneOrientation::Vertical)
+        } else {
+            None
+        };
+
+        Some(DetectedPlane {
+            id,
+            pose,
+            polygon,
+            orientation,
+            last_changed_time,
+        })
+    }
+
+    /// Whether two planes have the same pose, outline and orientation
+    pub fn same_geometry(&self, other: &DetectedPlane) -> bool {
+        self.pose.rotation == other.pose.rotation
+            && self.pose.translation == other.pose.translation
+            && self.polygon == other.polygon
+            && self.orientation == other.orientation
+    }
+}
+
+/// The rotation that takes the Y axis to the given unit normal
+fn rotation_from_normal(normal: Vector3D<f32, Native>) -> Rotation3D<f32, PlaneSpace, Native> {
+    let y = Vector3D::new(0., 1., 0.);
+    let dot = normal.dot(y);
+    if dot > 1. - EPSILON {
+        Rotation3D::identity()
+    } else if dot < -1. + EPSILON {
+        // Flipped upside down, any perpendicular axis will do
+        Rotation3D::quaternion(1., 0., 0., 0.)
+    } else {
+        let axis = y.cross(normal);
+        Rotation3D::quaternion(axis.x, axis.y, axis.z, 1. + dot).normalize()
+    }
+}
+
+/// Andrew's monotone chain, on the XZ plane
+/// https://en.wikibooks.org/wiki/Algorithm_Implementation/Geometry/Convex_hull/Monotone_chain
+fn convex_hull(mut points: Vec<Point3D<f32, PlaneSpace>>) -> Vec<Point3D<f32, PlaneSpace>> {
+    points.sort_by(|a, b| {
+        a.x.partial_cmp(&b.x)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.z.partial_cmp(&b.z).unwrap_or(std::cmp::Ordering::Equal))
+    });
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut hull = half_hull(points.iter());
+    hull.extend(half_hull(points.iter().rev()));
+    hull
+}
+
+fn half_hull<'a>(
+    points: impl Iterator<Item = &'a Point3D<f32, PlaneSpace>>,
+) -> Vec<Point3D<f32, PlaneSpace>> {
+    let mut hull: Vec<Point3D<f32, PlaneSpace>> = vec![];
+    for &point in points {
+        while hull.len() >= 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0. {
+            hull.pop();
+        }
+        hull.push(point);
+    }
+    // The last point is the first point of the other half
+    hull.pop();
+    hull
+}
+
+/// The Z component of the cross product of OA and OB, projected onto the XZ plane
+fn cross(
+    o: Point3D<f32, PlaneSpace>,
+    a: Point3D<f32, PlaneSpace>,
+    b: Point3D<f32, PlaneSpace>,
+) -> f32 {
+    (a.x - o.x) * (b.z - o.z) - (a.z - o.z) * (b.x - o.x)
+}
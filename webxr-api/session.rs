@@ -3,14 +3,23 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use crate::channel;
+use crate::AnchorId;
+use crate::AnchorSpace;
+use crate::ApiSpace;
 use crate::ContextId;
+use crate::DepthDataFormat;
+use crate::DepthSensing;
+use crate::DepthSensingInit;
+use crate::DepthUsage;
 use crate::DeviceAPI;
+use crate::DomOverlayType;
 use crate::Error;
 use crate::Event;
 use crate::Floor;
 use crate::Frame;
 use crate::FrameUpdateEvent;
 use crate::HitTestId;
+use crate::HitTestResult;
 use crate::HitTestSource;
 use crate::InputSource;
 use crate::LayerGrandManager;
@@ -19,6 +28,8 @@ use crate::LayerInit;
 use crate::Native;
 use crate::Receiver;
 use crate::Sender;
+use crate::Space;
+use crate::TransientHitTestSource;
 use crate::Viewport;
 use crate::Viewports;
 
@@ -38,6 +49,14 @@ use serde::{Deserialize, Serialize};
 // How long to wait for an rAF.
 static TIMEOUT: Duration = Duration::from_millis(5);
 
+/// The smallest viewport scale that can be requested
+pub const MIN_VIEWPORT_SCALE: f32 = 0.25;
+
+/// The viewport scale that is applied for a requested scale
+fn clamp_viewport_scale(scale: f32) -> f32 {
+    scale.max(MIN_VIEWPORT_SCALE).min(1.0)
+}
+
 /// https://www.w3.org/TR/webxr/#xrsessionmode-enum
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
@@ -57,6 +76,9 @@ pub struct SessionInit {
     /// but for performance reasons we also ask users to enable this pref
     /// for now.
     pub first_person_observer_view: bool,
+    /// Depth sensing preferences, used if the `depth-sensing` feature is granted
+    /// https://immersive-web.github.io/depth-sensing/#dom-xrsessioninit-depthsensing
+    pub depth_sensing: Option<DepthSensingInit>,
 }
 
 impl SessionInit {
@@ -117,8 +139,22 @@ enum SessionMsg {
     StartRenderLoop,
     RenderAnimationFrame,
     RequestHitTest(HitTestSource),
+    RequestTransientHitTest(TransientHitTestSource),
     CancelHitTest(HitTestId),
+    CreateAnchor(
+        Space,
+        RigidTransform3D<f32, ApiSpace, ApiSpace>,
+        Sender<Result<AnchorId, Error>>,
+    ),
+    CreateNativeAnchor(
+        RigidTransform3D<f32, AnchorSpace, Native>,
+        Sender<Result<AnchorId, Error>>,
+    ),
+    DeleteAnchor(AnchorId),
+    RequestLightProbe(Sender<Result<(), Error>>),
     UpdateFrameRate(f32, Sender<f32>),
+    RequestViewportScale(f32),
+    SetLayerFoveation(LayerId, f32, Sender<f32>),
     Quit,
     GetBoundsGeometry(Sender<Option<Vec<Point2D<f32, Floor>>>>),
 }
@@ -148,6 +184,9 @@ pub struct Session {
     granted_features: Vec<String>,
     id: SessionId,
     supported_frame_rates: Vec<f32>,
+    frame_rate: Option<f32>,
+    depth_sensing: Option<DepthSensing>,
+    dom_overlay_type: Option<DomOverlayType>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -177,6 +216,21 @@ impl Session {
         self.environment_blend_mode
     }
 
+    /// https://immersive-web.github.io/depth-sensing/#dom-xrsession-depthusage
+    pub fn depth_usage(&self) -> Option<DepthUsage> {
+        self.depth_sensing.map(|depth| depth.usage)
+    }
+
+    /// https://immersive-web.github.io/depth-sensing/#dom-xrsession-depthdataformat
+    pub fn depth_data_format(&self) -> Option<DepthDataFormat> {
+        self.depth_sensing.map(|depth| depth.data_format)
+    }
+
+    /// https://immersive-web.github.io/dom-overlays/#dom-xrsession-domoverlaystate
+    pub fn dom_overlay_type(&self) -> Option<DomOverlayType> {
+        self.dom_overlay_type
+    }
+
     pub fn viewports(&self) -> &[Rect<i32, Viewport>] {
         &self.viewports.viewports
     }
@@ -209,6 +263,17 @@ impl Session {
             .send(SessionMsg::DestroyLayer(context_id, layer_id));
     }
 
+    /// The level is clamped to between 0 and 1, and the level actually applied is returned
+    /// https://immersive-web.github.io/layers/#dom-xrcompositionlayer-fixedfoveation
+    pub fn set_layer_foveation(&self, layer_id: LayerId, level: f32) -> Result<f32, Error> {
+        let level = level.max(0.0).min(1.0);
+        let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
+        let _ = self
+            .sender
+            .send(SessionMsg::SetLayerFoveation(layer_id, level, sender));
+        receiver.recv().map_err(|_| Error::CommunicationError)
+    }
+
     pub fn set_layers(&self, layers: Vec<(ContextId, LayerId)>) {
         let _ = self.sender.send(SessionMsg::SetLayers(layers));
     }
@@ -238,6 +303,7 @@ impl Session {
             FrameUpdateEvent::UpdateFloorTransform(floor) => self.floor_transform = floor,
             FrameUpdateEvent::UpdateViewports(vp) => self.viewports = vp,
             FrameUpdateEvent::HitTestSourceAdded(_) => (),
+            FrameUpdateEvent::UpdateFrameRate(rate) => self.frame_rate = Some(rate),
         }
     }
 
@@ -249,10 +315,60 @@ impl Session {
         let _ = self.sender.send(SessionMsg::RequestHitTest(source));
     }
 
+    /// https://immersive-web.github.io/hit-test/#dom-xrsession-requesthittestsourcefortransientinput
+    pub fn request_transient_hit_test(&self, source: TransientHitTestSource) {
+        let _ = self
+            .sender
+            .send(SessionMsg::RequestTransientHitTest(source));
+    }
+
     pub fn cancel_hit_test(&self, id: HitTestId) {
         let _ = self.sender.send(SessionMsg::CancelHitTest(id));
     }
 
+    /// https://immersive-web.github.io/anchors/#dom-xrframe-createanchor
+    pub fn create_anchor(
+        &self,
+        space: Space,
+        pose: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+    ) -> Result<AnchorId, Error> {
+        let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
+        let _ = self
+            .sender
+            .send(SessionMsg::CreateAnchor(space, pose, sender));
+        receiver.recv().map_err(|_| Error::CommunicationError)?
+    }
+
+    /// https://immersive-web.github.io/anchors/#dom-xrhittestresult-createanchor
+    pub fn create_anchor_from_hit_test(&self, result: &HitTestResult) -> Result<AnchorId, Error> {
+        let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
+        let _ = self.sender.send(SessionMsg::CreateNativeAnchor(
+            result.space.cast_unit(),
+            sender,
+        ));
+        receiver.recv().map_err(|_| Error::CommunicationError)?
+    }
+
+    /// https://immersive-web.github.io/anchors/#dom-xranchor-delete
+    pub fn delete_anchor(&self, id: AnchorId) {
+        let _ = self.sender.send(SessionMsg::DeleteAnchor(id));
+    }
+
+    /// Once this succeeds, frames carry a `LightEstimate`
+    /// https://immersive-web.github.io/lighting-estimation/#dom-xrsession-requestlightprobe
+    pub fn request_light_probe(&self) -> Result<(), Error> {
+        let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
+        let _ = self.sender.send(SessionMsg::RequestLightProbe(sender));
+        receiver.recv().map_err(|_| Error::CommunicationError)?
+    }
+
+    /// The scale is clamped to between `MIN_VIEWPORT_SCALE` and 1, and applies from the next frame
+    /// https://immersive-web.github.io/webxr/#dom-xrview-requestviewportscale
+    pub fn request_viewport_scale(&self, scale: f32) {
+        let scale = clamp_viewport_scale(scale);
+        let _ = self.sender.send(SessionMsg::RequestViewportScale(scale));
+    }
+
     pub fn update_frame_rate(&mut self, rate: f32, sender: Sender<f32>) {
         let _ = self.sender.send(SessionMsg::UpdateFrameRate(rate, sender));
     }
@@ -260,6 +376,12 @@ impl Session {
     pub fn supported_frame_rates(&self) -> &[f32] {
         &self.supported_frame_rates
     }
+
+    /// The frame rate most recently reported by the device, if any
+    /// https://immersive-web.github.io/webxr/#dom-xrsession-framerate
+    pub fn frame_rate(&self) -> Option<f32> {
+        self.frame_rate
+    }
 }
 
 #[derive(PartialEq)]
@@ -322,6 +444,8 @@ where
         let environment_blend_mode = self.device.environment_blend_mode();
         let granted_features = self.device.granted_features().into();
         let supported_frame_rates = self.device.supported_frame_rates();
+        let depth_sensing = self.device.depth_sensing();
+        let dom_overlay_type = self.device.dom_overlay_type();
         Session {
             floor_transform,
             viewports,
@@ -331,6 +455,9 @@ where
             granted_features,
             id: self.id,
             supported_frame_rates,
+            frame_rate: None,
+            depth_sensing,
+            dom_overlay_type,
         }
     }
 
@@ -356,9 +483,27 @@ where
             SessionMsg::RequestHitTest(source) => {
                 self.device.request_hit_test(source);
             }
+            SessionMsg::RequestTransientHitTest(source) => {
+                self.device.request_transient_hit_test(source);
+            }
             SessionMsg::CancelHitTest(id) => {
                 self.device.cancel_hit_test(id);
             }
+            SessionMsg::CreateAnchor(space, pose, sender) => {
+                let result = self.device.create_anchor(space, pose);
+                let _ = sender.send(result);
+            }
+            SessionMsg::CreateNativeAnchor(pose, sender) => {
+                let result = self.device.create_native_anchor(pose);
+                let _ = sender.send(result);
+            }
+            SessionMsg::DeleteAnchor(id) => {
+                self.device.delete_anchor(id);
+            }
+            SessionMsg::RequestLightProbe(sender) => {
+                let result = self.device.request_light_probe();
+                let _ = sender.send(result);
+            }
             SessionMsg::CreateLayer(context_id, layer_init, sender) => {
                 let result = self.device.create_layer(context_id, layer_init);
                 let _ = sender.send(result);
@@ -413,6 +558,11 @@ where
                 let new_framerate = self.device.update_frame_rate(rate);
                 let _ = sender.send(new_framerate);
             }
+            SessionMsg::RequestViewportScale(scale) => self.device.request_viewport_scale(scale),
+            SessionMsg::SetLayerFoveation(layer_id, level, sender) => {
+                let level = self.device.set_layer_foveation(layer_id, level);
+                let _ = sender.send(level);
+            }
             SessionMsg::Quit => {
                 if self.render_state == RenderState::NotInRenderLoop {
                     self.quit();
@@ -529,3 +679,19 @@ impl<'a, GL: 'static> SessionBuilder<'a, GL> {
         Ok(session)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_scale_is_clamped() {
+        assert_eq!(clamp_viewport_scale(0.5), 0.5);
+        assert_eq!(clamp_viewport_scale(1.0), 1.0);
+        assert_eq!(clamp_viewport_scale(2.0), 1.0);
+        assert_eq!(clamp_viewport_scale(0.1), MIN_VIEWPORT_SCALE);
+        assert_eq!(clamp_viewport_scale(0.0), MIN_VIEWPORT_SCALE);
+        assert_eq!(clamp_viewport_scale(-1.0), MIN_VIEWPORT_SCALE);
+        assert_eq!(clamp_viewport_scale(f32::NAN), MIN_VIEWPORT_SCALE);
+    }
+}
@@ -1,7 +1,13 @@
+use crate::DetectedMesh;
 use crate::FrameUpdateEvent;
 use crate::HitTestId;
 use crate::HitTestSource;
+use crate::MeshId;
+use crate::MeshUpdate;
+use crate::TransientHitTestSource;
 use euclid::Transform3D;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
@@ -46,6 +52,8 @@ impl ClipPlanes {
 pub struct HitTestList {
     tests: Vec<HitTestSource>,
     uncommitted_tests: Vec<HitTestSource>,
+    transient_tests: Vec<TransientHitTestSource>,
+    uncommitted_transient_tests: Vec<TransientHitTestSource>,
 }
 
 impl HitTestList {
@@ -53,12 +61,20 @@ impl HitTestList {
         self.uncommitted_tests.push(source)
     }
 
+    pub fn request_transient_hit_test(&mut self, source: TransientHitTestSource) {
+        self.uncommitted_transient_tests.push(source)
+    }
+
     pub fn commit_tests(&mut self) -> Vec<FrameUpdateEvent> {
         let mut events = vec![];
         for test in self.uncommitted_tests.drain(..) {
             events.push(FrameUpdateEvent::HitTestSourceAdded(test.id));
             self.tests.push(test);
         }
+        for test in self.uncommitted_transient_tests.drain(..) {
+            events.push(FrameUpdateEvent::HitTestSourceAdded(test.id));
+            self.transient_tests.push(test);
+        }
         events
     }
 
@@ -66,9 +82,96 @@ impl HitTestList {
         &self.tests
     }
 
+    pub fn transient_tests(&self) -> &[TransientHitTestSource] {
+        &self.transient_tests
+    }
+
     pub fn cancel_hit_test(&mut self, id: HitTestId) {
         self.tests.retain(|s| s.id != id);
         self.uncommitted_tests.retain(|s| s.id != id);
+        self.transient_tests.retain(|s| s.id != id);
+        self.uncommitted_transient_tests.retain(|s| s.id != id);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// Keeps track of the meshes reported to the client, so that only changes need to be sent
+pub struct MeshList {
+    reported: HashMap<MeshId, DetectedMesh>,
+}
+
+impl MeshList {
+    /// Compare the currently detected meshes against the ones previously reported.
+    /// A mesh is updated if its change time or its geometry differs, since a device
+    /// may change a mesh more than once within the resolution of its clock.
+    pub fn update(&mut self, meshes: &[DetectedMesh]) -> Vec<MeshUpdate> {
+        let mut updates = vec![];
+        let mut reported = HashMap::new();
+        for mesh in meshes {
+            let previous = match self.reported.remove(&mesh.id) {
+                None => {
+                    updates.push(MeshUpdate::Added(mesh.clone()));
+                    mesh.clone()
+                }
+                Some(previous)
+                    if previous.last_changed_time != mesh.last_changed_time
+                        || !previous.same_geometry(mesh) =>
+                {
+                    updates.push(MeshUpdate::Updated(mesh.clone()));
+                    mesh.clone()
+                }
+                Some(previous) => previous,
+            };
+            reported.insert(mesh.id, previous);
+        }
+        // Anything we haven't seen this time around has been removed
+        updates.extend(self.reported.keys().map(|&id| MeshUpdate::Removed(id)));
+        self.reported = reported;
+        updates
+    }
+}
+
+/// The source of the time of a device's frames
+#[derive(Clone, Copy, Debug)]
+pub enum Clock {
+    /// Time passes in real time, starting from when the clock was created
+    RealTime(Instant),
+    /// Time only passes when the clock is advanced, so tests can control it exactly
+    Virtual(Duration),
+}
+
+impl Clock {
+    pub fn real_time() -> Clock {
+        Clock::RealTime(Instant::now())
+    }
+
+    pub fn virtual_time() -> Clock {
+        Clock::Virtual(Duration::default())
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        matches!(self, Clock::Virtual(_))
+    }
+
+    /// The time since the clock started
+    pub fn now(&self) -> Duration {
+        match *self {
+            Clock::RealTime(start) => start.elapsed(),
+            Clock::Virtual(now) => now,
+        }
+    }
+
+    /// Advance a virtual clock, which does nothing for a real time clock
+    pub fn advance(&mut self, duration: Duration) {
+        if let Clock::Virtual(ref mut now) = *self {
+            *now += duration;
+        }
+    }
+
+    /// The time since the clock started in nanoseconds, for `Frame::predicted_display_time`
+    pub fn now_nanos(&self) -> f64 {
+        self.now().as_nanos() as f64
     }
 }
 
@@ -127,3 +230,87 @@ pub fn frustum_to_projection_matrix<T, U>(
         0.,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Triangle;
+    use euclid::Point3D;
+
+    fn mesh(id: u32, height: f32, last_changed_time: f64) -> DetectedMesh {
+        let face = Triangle {
+            first: Point3D::new(0., height, 0.),
+            second: Point3D::new(1., height, 0.),
+            third: Point3D::new(0., height, 1.),
+        };
+        DetectedMesh::from_triangles(MeshId(id), &[face], None, last_changed_time)
+    }
+
+    #[test]
+    fn mesh_list_reports_new_meshes_once() {
+        let mut list = MeshList::default();
+        let meshes = [mesh(0, 0., 0.), mesh(1, 1., 0.)];
+        let updates = list.update(&meshes);
+        assert_eq!(updates.len(), 2);
+        assert!(updates
+            .iter()
+            .all(|update| matches!(update, MeshUpdate::Added(_))));
+        assert!(list.update(&meshes).is_empty());
+    }
+
+    #[test]
+    fn mesh_list_reports_changed_meshes() {
+        let mut list = MeshList::default();
+        list.update(&[mesh(0, 0., 0.), mesh(1, 1., 0.)]);
+
+        let updates = list.update(&[mesh(0, 0., 10.), mesh(1, 1., 0.)]);
+        assert!(matches!(updates[..], [MeshUpdate::Updated(ref mesh)] if mesh.id == MeshId(0)));
+
+        // Changes within the resolution of the clock are still reported
+        let updates = list.update(&[mesh(0, 0., 10.), mesh(1, 2., 0.)]);
+        assert!(matches!(updates[..], [MeshUpdate::Updated(ref mesh)] if mesh.id == MeshId(1)));
+        assert!(list.update(&[mesh(0, 0., 10.), mesh(1, 2., 0.)]).is_empty());
+    }
+
+    #[test]
+    fn mesh_list_reports_removed_meshes() {
+        let mut list = MeshList::default();
+        list.update(&[mesh(0, 0., 0.), mesh(1, 1., 0.)]);
+
+        let updates = list.update(&[mesh(1, 1., 0.)]);
+        assert!(matches!(updates[..], [MeshUpdate::Removed(MeshId(0))]));
+
+        // A mesh that comes back is added again
+        let updates = list.update(&[mesh(0, 0., 0.), mesh(1, 1., 0.)]);
+        assert!(matches!(updates[..], [MeshUpdate::Added(ref mesh)] if mesh.id == MeshId(0)));
+    }
+
+    #[test]
+    fn virtual_clock_only_moves_when_advanced() {
+        let mut clock = Clock::virtual_time();
+        assert!(clock.is_virtual());
+        assert_eq!(clock.now(), Duration::ZERO);
+        assert_eq!(clock.now(), Duration::ZERO);
+        clock.advance(Duration::from_millis(20));
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(clock.now(), Duration::from_millis(25));
+        assert_eq!(clock.now_nanos(), 25_000_000.);
+    }
+
+    #[test]
+    fn real_time_clock_ignores_advance() {
+        let mut clock = Clock::real_time();
+        assert!(!clock.is_virtual());
+        clock.advance(Duration::from_secs(3600));
+        assert!(clock.now() < Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn real_time_clock_never_goes_backwards() {
+        let clock = Clock::real_time();
+        let first = clock.now();
+        let second = clock.now();
+        assert!(second >= first);
+        assert!(clock.now_nanos() >= second.as_nanos() as f64);
+    }
+}
@@ -4,8 +4,13 @@
 
 //! This crate uses `euclid`'s typed units, and exposes different coordinate spaces.
 
+use crate::Camera;
+use crate::DepthInformation;
+
+use euclid::Point2D;
 use euclid::Rect;
 use euclid::RigidTransform3D;
+use euclid::Size2D;
 use euclid::Transform3D;
 
 #[cfg(feature = "ipc")]
@@ -75,6 +80,28 @@ pub const CUBE_TOP: SomeEye<CubeTop> = SomeEye(5, PhantomData);
 pub const CUBE_BOTTOM: SomeEye<CubeBottom> = SomeEye(6, PhantomData);
 pub const CUBE_BACK: SomeEye<CubeBack> = SomeEye(7, PhantomData);
 
+/// The layout of the faces of a cubemap in a 3x2 grid, in the order
+/// `VIEWER`, `CUBE_LEFT`, `CUBE_RIGHT`, `CUBE_TOP`, `CUBE_BOTTOM`, `CUBE_BACK`.
+pub fn cube_face_rects(face_size: Size2D<i32, Viewport>) -> [Rect<i32, Viewport>; 6] {
+    let (width, height) = (face_size.width, face_size.height);
+    [
+        Rect::new(Point2D::new(width, height), face_size),
+        Rect::new(Point2D::new(0, height), face_size),
+        Rect::new(Point2D::new(width * 2, height), face_size),
+        Rect::new(Point2D::new(width * 2, 0), face_size),
+        Rect::new(Point2D::new(0, 0), face_size),
+        Rect::new(Point2D::new(width, 0), face_size),
+    ]
+}
+
+/// A view's viewport shrunk by a viewport scale, keeping its origin
+/// https://immersive-web.github.io/webxr/#dom-xrview-requestviewportscale
+pub fn scale_viewport(viewport: Rect<i32, Viewport>, scale: f32) -> Rect<i32, Viewport> {
+    let size = viewport.size.to_f32() * scale;
+    let size = Size2D::new(size.width.round().max(1.0), size.height.round().max(1.0));
+    Rect::new(viewport.origin, size.to_i32())
+}
+
 impl<Eye1, Eye2> PartialEq<SomeEye<Eye2>> for SomeEye<Eye1> {
     fn eq(&self, rhs: &SomeEye<Eye2>) -> bool {
         self.0 == rhs.0
@@ -121,6 +148,12 @@ pub enum Capture {}
 pub struct View<Eye> {
     pub transform: RigidTransform3D<f32, Eye, Native>,
     pub projection: Transform3D<f32, Eye, Display>,
+    /// Enabled with the `depth-sensing` feature
+    /// https://immersive-web.github.io/depth-sensing/#xrview-extension
+    pub depth: Option<DepthInformation>,
+    /// Enabled with the `camera-access` feature
+    /// https://immersive-web.github.io/raw-camera-access/#dom-xrview-camera
+    pub camera: Option<Camera>,
 }
 
 impl<Eye> Default for View<Eye> {
@@ -128,6 +161,8 @@ impl<Eye> Default for View<Eye> {
         View {
             transform: RigidTransform3D::identity(),
             projection: Transform3D::identity(),
+            depth: None,
+            camera: None,
         }
     }
 }
@@ -137,6 +172,8 @@ impl<Eye> View<Eye> {
         View {
             transform: self.transform.cast_unit(),
             projection: Transform3D::from_untyped(&self.projection.to_untyped()),
+            depth: self.depth.clone(),
+            camera: self.camera,
         }
     }
 }
@@ -168,3 +205,26 @@ pub enum Views {
 pub struct Viewports {
     pub viewports: Vec<Rect<i32, Viewport>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect<i32, Viewport> {
+        Rect::new(Point2D::new(x, y), Size2D::new(width, height))
+    }
+
+    #[test]
+    fn scale_viewport_keeps_origin() {
+        let viewport = rect(100, 20, 100, 50);
+        assert_eq!(scale_viewport(viewport, 1.0), viewport);
+        assert_eq!(scale_viewport(viewport, 0.5), rect(100, 20, 50, 25));
+        assert_eq!(scale_viewport(viewport, 0.25), rect(100, 20, 25, 13));
+    }
+
+    #[test]
+    fn scale_viewport_is_never_empty() {
+        assert_eq!(scale_viewport(rect(8, 0, 2, 2), 0.1), rect(8, 0, 1, 1));
+        assert_eq!(scale_viewport(rect(0, 0, 1, 1), 0.25), rect(0, 0, 1, 1));
+    }
+}
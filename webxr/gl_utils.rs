@@ -3,13 +3,19 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use crate::SurfmanGL;
+use euclid::Size2D;
+use euclid::UnknownUnit;
 use glow as gl;
 use glow::Context as Gl;
 use glow::HasContext;
+use glow::PixelUnpackData;
 use std::collections::HashMap;
 use std::num::NonZero;
 use surfman::Device as SurfmanDevice;
+use surfman::GLApi;
 use webxr_api::ContextId;
+use webxr_api::DepthStencilFormat;
+use webxr_api::Error;
 use webxr_api::GLContexts;
 use webxr_api::LayerId;
 
@@ -17,13 +23,198 @@ pub(crate) fn framebuffer(framebuffer: u32) -> Option<gl::NativeFramebuffer> {
     NonZero::new(framebuffer).map(gl::NativeFramebuffer)
 }
 
-// A utility to clear a color texture and optional depth/stencil texture
+// The formats used to allocate a GL texture
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TextureFormat {
+    pub(crate) internal_format: u32,
+    pub(crate) format: u32,
+    pub(crate) ty: u32,
+}
+
+pub(crate) const RGBA8: TextureFormat = TextureFormat {
+    internal_format: gl::RGBA8,
+    format: gl::RGBA,
+    ty: gl::UNSIGNED_BYTE,
+};
+
+impl TextureFormat {
+    pub(crate) fn depth_stencil(format: DepthStencilFormat) -> TextureFormat {
+        let (internal_format, format, ty) = match format {
+            DepthStencilFormat::Depth24 => {
+                (gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::UNSIGNED_INT)
+            }
+            DepthStencilFormat::Depth32F => {
+                (gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT, gl::FLOAT)
+            }
+            DepthStencilFormat::Stencil8 => {
+                (gl::STENCIL_INDEX8, gl::STENCIL_INDEX, gl::UNSIGNED_BYTE)
+            }
+            DepthStencilFormat::Depth24Stencil8 => (
+                gl::DEPTH24_STENCIL8,
+                gl::DEPTH_STENCIL,
+                gl::UNSIGNED_INT_24_8,
+            ),
+            DepthStencilFormat::Depth32FStencil8 => (
+                gl::DEPTH32F_STENCIL8,
+                gl::DEPTH_STENCIL,
+                gl::FLOAT_32_UNSIGNED_INT_24_8_REV,
+            ),
+        };
+        TextureFormat {
+            internal_format,
+            format,
+            ty,
+        }
+    }
+}
+
+/// The depth/stencil format to allocate for a requested format. Stencil-only textures
+/// need GL 4.4, GLES 3.2 or `texture_stencil8`, so without them stencil is allocated
+/// along with depth instead.
+pub(crate) fn supported_depth_stencil_format(
+    gl: &Gl,
+    format: DepthStencilFormat,
+) -> DepthStencilFormat {
+    if format != DepthStencilFormat::Stencil8 {
+        return format;
+    }
+    let version = gl.version();
+    let extensions = gl.supported_extensions();
+    let supports_stencil8 = if version.is_embedded {
+        (version.major, version.minor) >= (3, 2) || extensions.contains("GL_OES_texture_stencil8")
+    } else {
+        (version.major, version.minor) >= (4, 4) || extensions.contains("GL_ARB_texture_stencil8")
+    };
+    if supports_stencil8 {
+        format
+    } else {
+        DepthStencilFormat::Depth24Stencil8
+    }
+}
+
+/// The framebuffer attachment point for a depth/stencil format
+pub(crate) fn depth_stencil_attachment(format: DepthStencilFormat) -> u32 {
+    match (format.has_depth(), format.has_stencil()) {
+        (true, true) => gl::DEPTH_STENCIL_ATTACHMENT,
+        (false, _) => gl::STENCIL_ATTACHMENT,
+        (true, false) => gl::DEPTH_ATTACHMENT,
+    }
+}
+
+/// Allocate an uninitialized 2D texture, or a texture array if `len` is the number of slices
+pub(crate) unsafe fn create_texture(
+    gl: &Gl,
+    format: TextureFormat,
+    width: i32,
+    height: i32,
+    len: Option<usize>,
+) -> Result<gl::NativeTexture, Error> {
+    let texture = gl.create_texture().map_err(Error::BackendSpecific)?;
+    let (target, binding) = match len {
+        Some(_) => (gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BINDING_2D_ARRAY),
+        None => (gl::TEXTURE_2D, gl::TEXTURE_BINDING_2D),
+    };
+    let mut bound_texture = [0];
+    gl.get_parameter_i32_slice(binding, &mut bound_texture);
+    gl.bind_texture(target, Some(texture));
+    match len {
+        Some(len) => gl.tex_image_3d(
+            target,
+            0,
+            format.internal_format as _,
+            width,
+            height,
+            len as _,
+            0,
+            format.format,
+            format.ty,
+            PixelUnpackData::Slice(None),
+        ),
+        None => gl.tex_image_2d(
+            target,
+            0,
+            format.internal_format as _,
+            width,
+            height,
+            0,
+            format.format,
+            format.ty,
+            PixelUnpackData::Slice(None),
+        ),
+    }
+    let bound_texture = NonZero::new(bound_texture[0] as u32).map(gl::NativeTexture);
+    gl.bind_texture(target, bound_texture);
+    Ok(texture)
+}
+
+/// Clear all the buffers of a framebuffer, preserving the rest of the GL state
+pub(crate) fn clear_framebuffer(gl: &Gl, fbo: Option<gl::NativeFramebuffer>) {
+    unsafe {
+        // Save the current GL state
+        let mut bound_fbos = [0, 0];
+        let mut clear_color = [0., 0., 0., 0.];
+        let mut clear_depth = [0.];
+        let mut clear_stencil = [0];
+        let color_mask;
+        let depth_mask;
+        let mut stencil_mask = [0];
+        let scissor_enabled = gl.is_enabled(gl::SCISSOR_TEST);
+        let rasterizer_enabled = gl.is_enabled(gl::RASTERIZER_DISCARD);
+
+        gl.get_parameter_i32_slice(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_fbos[0..]);
+        gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbos[1..]);
+        gl.get_parameter_f32_slice(gl::COLOR_CLEAR_VALUE, &mut clear_color[..]);
+        gl.get_parameter_f32_slice(gl::DEPTH_CLEAR_VALUE, &mut clear_depth[..]);
+        gl.get_parameter_i32_slice(gl::STENCIL_CLEAR_VALUE, &mut clear_stencil[..]);
+        depth_mask = gl.get_parameter_bool(gl::DEPTH_WRITEMASK);
+        gl.get_parameter_i32_slice(gl::STENCIL_WRITEMASK, &mut stencil_mask[..]);
+        color_mask = gl.get_parameter_bool_array::<4>(gl::COLOR_WRITEMASK);
+
+        // Clear it
+        gl.bind_framebuffer(gl::FRAMEBUFFER, fbo);
+        gl.clear_color(0., 0., 0., 1.);
+        gl.clear_depth(1.);
+        gl.clear_stencil(0);
+        gl.disable(gl::SCISSOR_TEST);
+        gl.disable(gl::RASTERIZER_DISCARD);
+        gl.depth_mask(true);
+        gl.stencil_mask(0xFFFFFFFF);
+        gl.color_mask(true, true, true, true);
+        gl.clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+
+        // Restore the GL state
+        gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer(bound_fbos[0] as _));
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbos[1] as _));
+        gl.clear_color(
+            clear_color[0],
+            clear_color[1],
+            clear_color[2],
+            clear_color[3],
+        );
+        gl.color_mask(color_mask[0], color_mask[1], color_mask[2], color_mask[3]);
+        gl.clear_depth(clear_depth[0] as f64);
+        gl.clear_stencil(clear_stencil[0]);
+        gl.depth_mask(depth_mask);
+        gl.stencil_mask(stencil_mask[0] as _);
+        if scissor_enabled {
+            gl.enable(gl::SCISSOR_TEST);
+        }
+        if rasterizer_enabled {
+            gl.enable(gl::RASTERIZER_DISCARD);
+        }
+        debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+    }
+}
+
+// A utility to clear a color texture and optional depth/stencil texture,
+// or one slice of a texture array
 pub(crate) struct GlClearer {
     fbos: HashMap<
         (
             LayerId,
             Option<gl::NativeTexture>,
             Option<gl::NativeTexture>,
+            Option<u32>,
         ),
         Option<gl::NativeFramebuffer>,
     >,
@@ -45,12 +236,17 @@ impl GlClearer {
         layer_id: LayerId,
         color: Option<gl::NativeTexture>,
         color_target: u32,
-        depth_stencil: Option<gl::NativeTexture>,
+        depth_stencil: Option<(gl::NativeTexture, DepthStencilFormat)>,
+        texture_array_index: Option<u32>,
     ) -> Option<gl::NativeFramebuffer> {
         let should_reverse_winding = self.should_reverse_winding;
+        let depth_stencil_texture = depth_stencil.map(|(texture, _)| texture);
+        let depth_stencil_attachment = depth_stencil
+            .map(|(_, format)| depth_stencil_attachment(format))
+            .unwrap_or(gl::DEPTH_STENCIL_ATTACHMENT);
         *self
             .fbos
-            .entry((layer_id, color, depth_stencil))
+            .entry((layer_id, color, depth_stencil_texture, texture_array_index))
             .or_insert_with(|| {
                 // Save the current GL state
                 let mut bound_fbos = [0, 0];
@@ -62,20 +258,37 @@ impl GlClearer {
                     let fbo = gl.create_framebuffer().ok();
 
                     gl.bind_framebuffer(gl::FRAMEBUFFER, fbo);
-                    gl.framebuffer_texture_2d(
-                        gl::FRAMEBUFFER,
-                        gl::COLOR_ATTACHMENT0,
-                        color_target,
-                        color,
-                        0,
-                    );
-                    gl.framebuffer_texture_2d(
-                        gl::FRAMEBUFFER,
-                        gl::DEPTH_STENCIL_ATTACHMENT,
-                        gl::TEXTURE_2D,
-                        depth_stencil,
-                        0,
-                    );
+                    if let Some(index) = texture_array_index {
+                        gl.framebuffer_texture_layer(
+                            gl::FRAMEBUFFER,
+                            gl::COLOR_ATTACHMENT0,
+                            color,
+                            0,
+                            index as i32,
+                        );
+                        gl.framebuffer_texture_layer(
+                            gl::FRAMEBUFFER,
+                            depth_stencil_attachment,
+                            depth_stencil_texture,
+                            0,
+                            index as i32,
+                        );
+                    } else {
+                        gl.framebuffer_texture_2d(
+                            gl::FRAMEBUFFER,
+                            gl::COLOR_ATTACHMENT0,
+                            color_target,
+                            color,
+                            0,
+                        );
+                        gl.framebuffer_texture_2d(
+                            gl::FRAMEBUFFER,
+                            depth_stencil_attachment,
+                            gl::TEXTURE_2D,
+                            depth_stencil_texture,
+                            0,
+                        );
+                    }
 
                     // Necessary if using an OpenXR runtime that does not support mutable FOV,
                     // as flipping the projection matrix necessitates reversing the winding order.
@@ -101,90 +314,258 @@ impl GlClearer {
         layer_id: LayerId,
         color: Option<glow::NativeTexture>,
         color_target: u32,
-        depth_stencil: Option<glow::NativeTexture>,
+        depth_stencil: Option<(glow::NativeTexture, DepthStencilFormat)>,
+        texture_array_index: Option<u32>,
     ) {
         let gl = match contexts.bindings(device, context_id) {
             None => return,
             Some(gl) => gl,
         };
-        let fbo = self.fbo(gl, layer_id, color, color_target, depth_stencil);
+        let fbo = self.fbo(
+            gl,
+            layer_id,
+            color,
+            color_target,
+            depth_stencil,
+            texture_array_index,
+        );
+        clear_framebuffer(gl, fbo);
+    }
+
+    pub(crate) fn destroy_layer(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+    ) {
+        let gl = match contexts.bindings(device, context_id) {
+            None => return,
+            Some(gl) => gl,
+        };
+        self.fbos.retain(|&(other_id, _, _, _), &mut fbo| {
+            if layer_id != other_id {
+                true
+            } else {
+                if let Some(fbo) = fbo {
+                    unsafe { gl.delete_framebuffer(fbo) };
+                }
+                false
+            }
+        })
+    }
+}
+
+// A utility to copy a depth texture into a color texture, so it can be shared
+// with a compositor through a surfman surface. Depth is packed as 24 bit fixed
+// point in the red, green and blue channels, which `unpack_depth` in a shader reverses.
+pub(crate) struct DepthPacker {
+    programs: HashMap<
+        ContextId,
+        (
+            gl::NativeProgram,
+            Option<gl::NativeVertexArray>,
+            Option<gl::NativeFramebuffer>,
+        ),
+    >,
+}
+
+const DEPTH_PACKER_VERTEX_SHADER: &str = "
+  out vec2 vTexCoord;
+  void main(void) {
+    vec2 coord = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+    gl_Position = vec4(coord * 2.0 - 1.0, 0.0, 1.0);
+    vTexCoord = coord;
+  }
+";
+
+const DEPTH_PACKER_FRAGMENT_SHADER: &str = "
+  layout(location=0) out vec4 color;
+  uniform sampler2D depth;
+  in vec2 vTexCoord;
+  void main() {
+    // Stop the far plane wrapping round to zero
+    float value = min(texture(depth, vTexCoord).r, 0.99999994);
+    vec3 packed = fract(value * vec3(1.0, 255.0, 65025.0));
+    packed -= packed.yzz * vec3(1.0/255.0, 1.0/255.0, 0.0);
+    color = vec4(packed, 1.0);
+  }
+";
+
+impl DepthPacker {
+    pub(crate) fn new() -> DepthPacker {
+        let programs = HashMap::new();
+        DepthPacker { programs }
+    }
+
+    fn program(
+        &mut self,
+        gl: &Gl,
+        gl_api: GLApi,
+        context_id: ContextId,
+    ) -> Result<
+        (
+            gl::NativeProgram,
+            Option<gl::NativeVertexArray>,
+            Option<gl::NativeFramebuffer>,
+        ),
+        Error,
+    > {
+        if let Some(&program) = self.programs.get(&context_id) {
+            return Ok(program);
+        }
+        let header = match gl_api {
+            GLApi::GL => "#version 330 core\n",
+            GLApi::GLES => "#version 300 es\nprecision highp float;\n",
+        };
+        unsafe {
+            let program = gl.create_program().map_err(Error::BackendSpecific)?;
+            for (shader_type, source) in [
+                (gl::VERTEX_SHADER, DEPTH_PACKER_VERTEX_SHADER),
+                (gl::FRAGMENT_SHADER, DEPTH_PACKER_FRAGMENT_SHADER),
+            ] {
+                let shader = gl
+                    .create_shader(shader_type)
+                    .map_err(Error::BackendSpecific)?;
+                gl.shader_source(shader, &format!("{}{}", header, source));
+                gl.compile_shader(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    let log = gl.get_shader_info_log(shader);
+                    gl.delete_shader(shader);
+                    gl.delete_program(program);
+                    return Err(Error::BackendSpecific(log));
+                }
+                gl.attach_shader(program, shader);
+                gl.delete_shader(shader);
+            }
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                let log = gl.get_program_info_log(program);
+                gl.delete_program(program);
+                return Err(Error::BackendSpecific(log));
+            }
+            let vao = gl.create_vertex_array().ok();
+            let fbo = gl.create_framebuffer().ok();
+            self.programs.insert(context_id, (program, vao, fbo));
+            Ok((program, vao, fbo))
+        }
+    }
+
+    /// Pack a depth texture into a color texture of the same size
+    pub(crate) fn pack(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        depth: gl::NativeTexture,
+        color: Option<gl::NativeTexture>,
+        color_target: u32,
+        size: Size2D<i32, UnknownUnit>,
+    ) -> Result<(), Error> {
+        let gl_api = device.gl_api();
+        let gl = contexts
+            .bindings(device, context_id)
+            .ok_or(Error::NoMatchingDevice)?;
+        let (program, vao, fbo) = self.program(gl, gl_api, context_id)?;
         unsafe {
             // Save the current GL state
             let mut bound_fbos = [0, 0];
-            let mut clear_color = [0., 0., 0., 0.];
-            let mut clear_depth = [0.];
-            let mut clear_stencil = [0];
-            let color_mask;
-            let depth_mask;
-            let mut stencil_mask = [0];
-            let scissor_enabled = gl.is_enabled(gl::SCISSOR_TEST);
-            let rasterizer_enabled = gl.is_enabled(gl::RASTERIZER_DISCARD);
-
+            let mut bound_program = [0];
+            let mut bound_vao = [0];
+            let mut active_texture = [0];
+            let mut bound_texture = [0];
+            let mut viewport = [0, 0, 0, 0];
             gl.get_parameter_i32_slice(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_fbos[0..]);
             gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbos[1..]);
-            gl.get_parameter_f32_slice(gl::COLOR_CLEAR_VALUE, &mut clear_color[..]);
-            gl.get_parameter_f32_slice(gl::DEPTH_CLEAR_VALUE, &mut clear_depth[..]);
-            gl.get_parameter_i32_slice(gl::STENCIL_CLEAR_VALUE, &mut clear_stencil[..]);
-            depth_mask = gl.get_parameter_bool(gl::DEPTH_WRITEMASK);
-            gl.get_parameter_i32_slice(gl::STENCIL_WRITEMASK, &mut stencil_mask[..]);
-            color_mask = gl.get_parameter_bool_array::<4>(gl::COLOR_WRITEMASK);
-
-            // Clear it
-            gl.bind_framebuffer(gl::FRAMEBUFFER, fbo);
-            gl.clear_color(0., 0., 0., 1.);
-            gl.clear_depth(1.);
-            gl.clear_stencil(0);
-            gl.disable(gl::SCISSOR_TEST);
-            gl.disable(gl::RASTERIZER_DISCARD);
-            gl.depth_mask(true);
-            gl.stencil_mask(0xFFFFFFFF);
+            gl.get_parameter_i32_slice(gl::CURRENT_PROGRAM, &mut bound_program);
+            gl.get_parameter_i32_slice(gl::VERTEX_ARRAY_BINDING, &mut bound_vao);
+            gl.get_parameter_i32_slice(gl::ACTIVE_TEXTURE, &mut active_texture);
+            gl.active_texture(gl::TEXTURE0);
+            gl.get_parameter_i32_slice(gl::TEXTURE_BINDING_2D, &mut bound_texture);
+            gl.get_parameter_i32_slice(gl::VIEWPORT, &mut viewport);
+            let color_mask = gl.get_parameter_bool_array::<4>(gl::COLOR_WRITEMASK);
+            let capabilities = [
+                gl::BLEND,
+                gl::CULL_FACE,
+                gl::DEPTH_TEST,
+                gl::RASTERIZER_DISCARD,
+                gl::SCISSOR_TEST,
+                gl::STENCIL_TEST,
+            ];
+            let enabled = capabilities.map(|capability| gl.is_enabled(capability));
+
+            // Draw the depth into the color texture
+            for capability in capabilities {
+                gl.disable(capability);
+            }
             gl.color_mask(true, true, true, true);
-            gl.clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+            gl.bind_framebuffer(gl::FRAMEBUFFER, fbo);
+            gl.framebuffer_texture_2d(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                color_target,
+                color,
+                0,
+            );
+            gl.viewport(0, 0, size.width, size.height);
+            gl.use_program(Some(program));
+            gl.bind_vertex_array(vao);
+            gl.bind_texture(gl::TEXTURE_2D, Some(depth));
+            // The depth texture belongs to the content, so its filters are put back afterwards
+            let min_filter = gl.get_tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER);
+            let mag_filter = gl.get_tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER);
+            gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+            gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
+            let depth_location = gl.get_uniform_location(program, "depth");
+            gl.uniform_1_i32(depth_location.as_ref(), 0);
+            gl.draw_arrays(gl::TRIANGLE_STRIP, 0, 4);
 
             // Restore the GL state
+            gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter);
+            gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter);
+            for (&capability, &enabled) in capabilities.iter().zip(&enabled) {
+                if enabled {
+                    gl.enable(capability);
+                }
+            }
+            gl.color_mask(color_mask[0], color_mask[1], color_mask[2], color_mask[3]);
+            gl.bind_texture(
+                gl::TEXTURE_2D,
+                NonZero::new(bound_texture[0] as u32).map(gl::NativeTexture),
+            );
+            gl.active_texture(active_texture[0] as u32);
+            gl.bind_vertex_array(NonZero::new(bound_vao[0] as u32).map(gl::NativeVertexArray));
+            gl.use_program(NonZero::new(bound_program[0] as u32).map(gl::NativeProgram));
+            gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
             gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer(bound_fbos[0] as _));
             gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbos[1] as _));
-            gl.clear_color(
-                clear_color[0],
-                clear_color[1],
-                clear_color[2],
-                clear_color[3],
-            );
-            gl.color_mask(color_mask[0], color_mask[1], color_mask[2], color_mask[3]);
-            gl.clear_depth(clear_depth[0] as f64);
-            gl.clear_stencil(clear_stencil[0]);
-            gl.depth_mask(depth_mask);
-            gl.stencil_mask(stencil_mask[0] as _);
-            if scissor_enabled {
-                gl.enable(gl::SCISSOR_TEST);
-            }
-            if rasterizer_enabled {
-                gl.enable(gl::RASTERIZER_DISCARD);
-            }
             debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
         }
+        Ok(())
     }
 
-    pub(crate) fn destroy_layer(
+    pub(crate) fn destroy_context(
         &mut self,
         device: &mut SurfmanDevice,
         contexts: &mut dyn GLContexts<SurfmanGL>,
         context_id: ContextId,
-        layer_id: LayerId,
     ) {
+        let (program, vao, fbo) = match self.programs.remove(&context_id) {
+            Some(program) => program,
+            None => return,
+        };
         let gl = match contexts.bindings(device, context_id) {
             None => return,
             Some(gl) => gl,
         };
-        self.fbos.retain(|&(other_id, _, _), &mut fbo| {
-            if layer_id != other_id {
-                true
-            } else {
-                if let Some(fbo) = fbo {
-                    unsafe { gl.delete_framebuffer(fbo) };
-                }
-                false
+        unsafe {
+            gl.delete_program(program);
+            if let Some(vao) = vao {
+                gl.delete_vertex_array(vao);
             }
-        })
+            if let Some(fbo) = fbo {
+                gl.delete_framebuffer(fbo);
+            }
+        }
     }
 }
@@ -9,21 +9,23 @@ use euclid::{
     Angle, Point2D, Rect, RigidTransform3D, Rotation3D, Size2D, Transform3D, UnknownUnit, Vector3D,
 };
 use glow::{self as gl, Context as Gl, HasContext};
+use std::collections::HashMap;
 use std::ffi::c_void;
 use std::num::NonZeroU32;
 use std::rc::Rc;
 use surfman::chains::{PreserveBuffer, SwapChain, SwapChainAPI, SwapChains, SwapChainsAPI};
 use surfman::{
-    Adapter, Connection, Context as SurfmanContext, ContextAttributes, Device as SurfmanDevice,
-    GLApi, NativeWidget, SurfaceAccess, SurfaceType,
+    Adapter, Connection, Context as SurfmanContext, ContextAttributeFlags, ContextAttributes,
+    Device as SurfmanDevice, GLApi, NativeWidget, SurfaceAccess, SurfaceType,
 };
-use webxr_api::util::ClipPlanes;
+use webxr_api::util::{ClipPlanes, Clock};
 use webxr_api::{
-    ContextId, DeviceAPI, DiscoveryAPI, Display, Error, Event, EventBuffer, Floor, Frame,
-    InputSource, LayerGrandManager, LayerId, LayerInit, LayerManager, Native, Quitter, Sender,
-    Session, SessionBuilder, SessionInit, SessionMode, SomeEye, View, Viewer, ViewerPose, Viewport,
-    Viewports, Views, CUBE_BACK, CUBE_BOTTOM, CUBE_LEFT, CUBE_RIGHT, CUBE_TOP, LEFT_EYE, RIGHT_EYE,
-    VIEWER,
+    cube_face_rects, scale_viewport, ApiSpace, BaseSpace, ContextId, DeviceAPI, DiscoveryAPI,
+    Display, DomOverlayType, Error, Event, EventBuffer, Floor, Frame, InputSource,
+    LayerGrandManager, LayerId, LayerInit, LayerLayout, LayerManager, Native, Quitter, Sender,
+    Session, SessionBuilder, SessionInit, SessionMode, SomeEye, Space, TextureType, View, Viewer,
+    ViewerPose, Viewport, Viewports, Views, CUBE_BACK, CUBE_BOTTOM, CUBE_LEFT, CUBE_RIGHT,
+    CUBE_TOP, LEFT_EYE, RIGHT_EYE, VIEWER,
 };
 
 // How far off the ground are the viewer's eyes?
@@ -99,7 +101,8 @@ impl DiscoveryAPI<SurfmanGL> for GlWindowDiscovery {
         xr: SessionBuilder<SurfmanGL>,
     ) -> Result<Session, Error> {
         if self.supports_session(mode) {
-            let granted_features = init.validate(mode, &["local-floor".into()])?;
+            let granted_features =
+                init.validate(mode, &["local-floor".into(), "dom-overlay".into()])?;
             let connection = self.connection.clone();
             let adapter = self.adapter.clone();
             let context_attributes = self.context_attributes.clone();
@@ -133,11 +136,31 @@ pub struct GlWindowDevice {
     layer_manager: Option<LayerManager>,
     target_swap_chain: Option<SwapChain<SurfmanDevice>>,
     swap_chains: SwapChains<LayerId, SurfmanDevice>,
+    /// The packed depth of layers that submit depth
+    depth_swap_chains: SwapChains<LayerId, SurfmanDevice>,
+    /// Whether the window has a depth buffer, so layers can be composited by depth
+    has_depth_buffer: bool,
     read_fbo: Option<gl::NativeFramebuffer>,
     events: EventBuffer,
     clip_planes: ClipPlanes,
+    /// The clip planes of the current frame, which submitted depth was rendered with
+    frame_clip_planes: ClipPlanes,
     granted_features: Vec<String>,
     shader: Option<GlWindowShader>,
+    overlay_shader: GlWindowShader,
+    quad_shader: GlWindowShader,
+    cylinder_shader: GlWindowShader,
+    equirect_shader: GlWindowShader,
+    cube_shader: GlWindowShader,
+    depth_shader: GlWindowShader,
+    layer_inits: HashMap<LayerId, LayerInit>,
+    /// The viewer pose of the current frame, used to position layers in viewer space
+    viewer: RigidTransform3D<f32, Viewer, Native>,
+    /// The viewport scale of projection layers, which is applied from the next frame
+    viewport_scale: f32,
+    /// The viewport scale of projection layers in the current frame
+    frame_viewport_scale: f32,
+    clock: Clock,
 }
 
 impl DeviceAPI for GlWindowDevice {
@@ -149,14 +172,7 @@ impl DeviceAPI for GlWindowDevice {
     fn viewports(&self) -> Viewports {
         let size = self.viewport_size();
         let viewports = match self.window.get_mode() {
-            GlWindowMode::Cubemap | GlWindowMode::Spherical => vec![
-                Rect::new(Point2D::new(size.width * 1, size.height * 1), size),
-                Rect::new(Point2D::new(size.width * 0, size.height * 1), size),
-                Rect::new(Point2D::new(size.width * 2, size.height * 1), size),
-                Rect::new(Point2D::new(size.width * 2, size.height * 0), size),
-                Rect::new(Point2D::new(size.width * 0, size.height * 0), size),
-                Rect::new(Point2D::new(size.width * 1, size.height * 0), size),
-            ],
+            GlWindowMode::Cubemap | GlWindowMode::Spherical => cube_face_rects(size).to_vec(),
             GlWindowMode::Blit | GlWindowMode::StereoLeftRight | GlWindowMode::StereoRedCyan => {
                 vec![
                     Rect::new(Point2D::default(), size),
@@ -168,10 +184,13 @@ impl DeviceAPI for GlWindowDevice {
     }
 
     fn create_layer(&mut self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
-        self.layer_manager()?.create_layer(context_id, init)
+        let layer_id = self.layer_manager()?.create_layer(context_id, init)?;
+        self.layer_inits.insert(layer_id, init);
+        Ok(layer_id)
     }
 
     fn destroy_layer(&mut self, context_id: ContextId, layer_id: LayerId) {
+        self.layer_inits.remove(&layer_id);
         self.layer_manager()
             .unwrap()
             .destroy_layer(context_id, layer_id)
@@ -185,6 +204,9 @@ impl DeviceAPI for GlWindowDevice {
         let rotation = Rotation3D::from_untyped(&self.window.get_rotation());
         let rotation = RigidTransform3D::from_rotation(rotation);
         let transform = translation.then(&rotation);
+        self.viewer = transform;
+        self.frame_viewport_scale = self.viewport_scale;
+        self.frame_clip_planes = self.clip_planes;
         let sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
         Some(Frame {
             pose: Some(ViewerPose {
@@ -195,7 +217,12 @@ impl DeviceAPI for GlWindowDevice {
             events: vec![],
             sub_images,
             hit_test_results: vec![],
-            predicted_display_time: 0.0,
+            transient_hit_test_results: vec![],
+            anchor_poses: vec![],
+            detected_planes: vec![],
+            mesh_updates: vec![],
+            light_estimate: None,
+            predicted_display_time: self.clock.now_nanos(),
         })
     }
 
@@ -227,11 +254,20 @@ impl DeviceAPI for GlWindowDevice {
             );
 
             self.gl.clear_color(0.0, 0.0, 0.0, 0.0);
-            self.gl.clear(gl::COLOR_BUFFER_BIT);
+            self.gl.clear_depth(1.0);
+            self.gl.clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
             debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
         }
 
-        for &(_, layer_id) in layers {
+        // Cube layers are composited behind everything else, and overlays on top
+        let mut layers: Vec<_> = layers.iter().collect();
+        layers.sort_by_key(|(_, layer_id)| match self.layer_inits.get(layer_id) {
+            Some(LayerInit::CubeLayer { .. }) => 0,
+            Some(LayerInit::Overlay { .. }) => 2,
+            _ => 1,
+        });
+
+        for &&(_, layer_id) in &layers {
             let swap_chain = match self.swap_chains.get(layer_id) {
                 Some(swap_chain) => swap_chain,
                 None => continue,
@@ -250,16 +286,105 @@ impl DeviceAPI for GlWindowDevice {
             let texture_target = self.device.surface_gl_texture_target();
             log::debug!("Presenting texture {}", raw_texture_id);
 
-            if let Some(ref shader) = self.shader {
-                shader.draw_texture(
-                    texture_id,
-                    texture_target,
-                    texture_size,
-                    viewport_size,
-                    window_size,
-                );
-            } else {
-                self.blit_texture(texture_id, texture_target, texture_size, window_size);
+            // The packed depth of layers that submit it
+            let depth_swap_chain = self.depth_swap_chains.get(layer_id);
+            let depth_surface_texture = depth_swap_chain
+                .as_ref()
+                .and_then(|depth_swap_chain| depth_swap_chain.take_surface())
+                .map(|surface| {
+                    self.device
+                        .create_surface_texture(&mut self.context, surface)
+                        .unwrap()
+                });
+            let depth_texture_id = depth_surface_texture
+                .as_ref()
+                .and_then(|texture| NonZeroU32::new(self.device.surface_texture_object(texture)))
+                .map(gl::NativeTexture);
+            let depth = depth_texture_id
+                .filter(|_| self.has_depth_buffer)
+                .map(|depth_texture_id| (depth_texture_id, self.frame_clip_planes));
+
+            match self.layer_inits.get(&layer_id) {
+                Some(&LayerInit::Overlay { alpha, .. }) => {
+                    unsafe {
+                        if !alpha {
+                            self.gl.disable(gl::BLEND);
+                        }
+                    }
+                    self.overlay_shader.draw_texture(
+                        texture_id,
+                        texture_target,
+                        texture_size,
+                        window_size,
+                        window_size,
+                        1.0,
+                        None,
+                    );
+                    unsafe {
+                        self.gl.enable(gl::BLEND);
+                    }
+                }
+                Some(init) if init.world_pose().is_some() => {
+                    self.draw_world_layer(
+                        init,
+                        texture_id,
+                        texture_target,
+                        texture_size,
+                        viewport_size,
+                    );
+                }
+                // Texture array layers are unpacked into the device's layout by the layer manager,
+                // since this device's context doesn't share the content's array textures
+                Some(
+                    init @ &LayerInit::ProjectionLayer {
+                        layout,
+                        texture_type: TextureType::Texture,
+                        ..
+                    },
+                ) if layout != LayerLayout::Default => {
+                    let rects = init.view_sub_image_rects(&self.viewports());
+                    let rects = self.scale_viewports(&rects);
+                    self.blit_views(texture_id, texture_target, &rects, window_size);
+                }
+                // Scaled projection layers only fill the corner of each view
+                Some(init)
+                    if init.is_projection()
+                        && self.frame_viewport_scale < 1.0
+                        && self.shader.is_none()
+                        && depth.is_none() =>
+                {
+                    let rects = self.scale_viewports(&self.viewports().viewports);
+                    self.blit_views(texture_id, texture_target, &rects, window_size);
+                }
+                init => {
+                    let viewport_scale = match init {
+                        Some(init) if init.is_projection() => self.frame_viewport_scale,
+                        _ => 1.0,
+                    };
+                    if let Some(ref shader) = self.shader {
+                        shader.draw_texture(
+                            texture_id,
+                            texture_target,
+                            texture_size,
+                            viewport_size,
+                            window_size,
+                            viewport_scale,
+                            depth,
+                        );
+                    } else if depth.is_some() {
+                        self.depth_shader.draw_texture(
+                            texture_id,
+                            texture_target,
+                            texture_size,
+                            viewport_size,
+                            window_size,
+                            viewport_scale,
+                            depth,
+                        );
+                    } else {
+                        self.blit_texture(texture_id, texture_target, texture_size, window_size);
+                    }
+                }
             }
             debug_assert_eq!(unsafe { self.gl.get_error() }, gl::NO_ERROR);
 
@@ -268,6 +393,15 @@ impl DeviceAPI for GlWindowDevice {
                 .destroy_surface_texture(&mut self.context, surface_texture)
                 .unwrap();
             swap_chain.recycle_surface(surface);
+            if let (Some(depth_swap_chain), Some(depth_surface_texture)) =
+                (depth_swap_chain, depth_surface_texture)
+            {
+                let surface = self
+                    .device
+                    .destroy_surface_texture(&mut self.context, depth_surface_texture)
+                    .unwrap();
+                depth_swap_chain.recycle_surface(surface);
+            }
         }
 
         match self.target_swap_chain.as_ref() {
@@ -321,6 +455,22 @@ impl DeviceAPI for GlWindowDevice {
     fn granted_features(&self) -> &[String] {
         &self.granted_features
     }
+
+    fn request_viewport_scale(&mut self, scale: f32) {
+        self.viewport_scale = scale;
+        if let Ok(layer_manager) = self.layer_manager() {
+            layer_manager.set_viewport_scale(scale);
+        }
+    }
+
+    fn dom_overlay_type(&self) -> Option<DomOverlayType> {
+        // Overlays cover the whole window, like a handheld AR device's screen
+        if self.granted_features.iter().any(|f| f == "dom-overlay") {
+            Some(DomOverlayType::Screen)
+        } else {
+            None
+        }
+    }
 }
 
 impl Drop for GlWindowDevice {
@@ -401,9 +551,19 @@ impl GlWindowDevice {
         }
 
         let swap_chains = SwapChains::new();
+        let depth_swap_chains = SwapChains::new();
+        let has_depth_buffer = context_attributes
+            .flags
+            .contains(ContextAttributeFlags::DEPTH);
         let layer_manager = None;
 
         let shader = GlWindowShader::new(gl.clone(), window.get_mode());
+        let overlay_shader = GlWindowShader::overlay(gl.clone());
+        let quad_shader = GlWindowShader::quad(gl.clone());
+        let cylinder_shader = GlWindowShader::cylinder(gl.clone());
+        let equirect_shader = GlWindowShader::equirect(gl.clone());
+        let cube_shader = GlWindowShader::cube(gl.clone());
+        let depth_shader = GlWindowShader::depth(gl.clone());
         debug_assert_eq!(unsafe { gl.get_error() }, gl::NO_ERROR);
 
         Ok(GlWindowDevice {
@@ -413,13 +573,27 @@ impl GlWindowDevice {
             context,
             read_fbo,
             swap_chains,
+            depth_swap_chains,
+            has_depth_buffer,
             target_swap_chain,
             grand_manager,
             layer_manager,
             events: Default::default(),
             clip_planes: Default::default(),
+            frame_clip_planes: Default::default(),
             granted_features,
             shader,
+            overlay_shader,
+            quad_shader,
+            cylinder_shader,
+            equirect_shader,
+            cube_shader,
+            depth_shader,
+            layer_inits: HashMap::new(),
+            viewer: RigidTransform3D::identity(),
+            viewport_scale: 1.0,
+            frame_viewport_scale: 1.0,
+            clock: Clock::real_time(),
         })
     }
 
@@ -455,14 +629,222 @@ impl GlWindowDevice {
         }
     }
 
+    /// Blit each view's sub image to where that view's viewport is in the window.
+    /// This is used for layers whose layout doesn't match the device's viewports,
+    /// and skips any shader for the window's mode.
+    fn blit_views(
+        &self,
+        texture_id: Option<gl::NativeTexture>,
+        texture_target: u32,
+        rects: &[Rect<i32, Viewport>],
+        window_size: Size2D<i32, Viewport>,
+    ) {
+        let viewports = self.viewports().viewports;
+        let native_size = viewports
+            .iter()
+            .fold(Rect::zero(), |acc, view| acc.union(view))
+            .size
+            .to_f32();
+        let scale_x = window_size.width as f32 / native_size.width;
+        let scale_y = window_size.height as f32 / native_size.height;
+        unsafe {
+            self.gl
+                .bind_framebuffer(gl::READ_FRAMEBUFFER, self.read_fbo);
+            self.gl.framebuffer_texture_2d(
+                gl::READ_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                texture_target,
+                texture_id,
+                0,
+            );
+            for (rect, viewport) in rects.iter().zip(&viewports) {
+                let viewport = viewport.to_f32().scale(scale_x, scale_y).to_i32();
+                self.gl.blit_framebuffer(
+                    rect.min_x(),
+                    rect.min_y(),
+                    rect.max_x(),
+                    rect.max_y(),
+                    viewport.min_x(),
+                    viewport.min_y(),
+                    viewport.max_x(),
+                    viewport.max_y(),
+                    gl::COLOR_BUFFER_BIT,
+                    gl::NEAREST,
+                );
+            }
+        }
+    }
+
+    /// The viewports of projection layers' views in the current frame
+    fn scale_viewports(&self, viewports: &[Rect<i32, Viewport>]) -> Vec<Rect<i32, Viewport>> {
+        viewports
+            .iter()
+            .map(|&viewport| scale_viewport(viewport, self.frame_viewport_scale))
+            .collect()
+    }
+
+    /// Draw a quad, cylinder, equirect or cube layer into each eye's region of the window
+    fn draw_world_layer(
+        &self,
+        init: &LayerInit,
+        texture_id: Option<gl::NativeTexture>,
+        texture_target: u32,
+        texture_size: Size2D<i32, UnknownUnit>,
+        viewport_size: Size2D<i32, Viewport>,
+    ) {
+        let (space, transform) = match init.world_pose() {
+            Some(pose) => pose,
+            None => return,
+        };
+        let (left, right) = match self.views(self.viewer) {
+            Views::Stereo(left, right) => (left, right),
+            _ => {
+                log::debug!(
+                    "World layers are not supported in {:?} mode",
+                    self.window.get_mode()
+                );
+                return;
+            }
+        };
+        let origin = match self.native_origin(space) {
+            Some(origin) => origin,
+            None => {
+                log::debug!("Layer space {:?} is not tracked", space.base);
+                return;
+            }
+        };
+        let pose = transform.then(&origin);
+        let rects = init.view_sub_image_rects(&self.viewports());
+        let texture_size = texture_size.to_f32();
+        let tex_rect = |rect: Rect<i32, Viewport>| {
+            let rect = rect.to_f32();
+            [
+                rect.origin.x / texture_size.width,
+                rect.origin.y / texture_size.height,
+                rect.size.width / texture_size.width,
+                rect.size.height / texture_size.height,
+            ]
+        };
+        let eyes = [
+            (layer_to_clip(&left, pose), tex_rect(rects[0])),
+            (layer_to_clip(&right, pose), tex_rect(rects[1])),
+        ];
+        for (i, (to_clip, tex_rect)) in eyes.iter().enumerate() {
+            let viewport = Rect::new(
+                Point2D::new(viewport_size.width * i as i32, 0),
+                viewport_size,
+            );
+            match *init {
+                LayerInit::QuadLayer { width, height, .. } => {
+                    // The quad shader maps the [-1, 1] square to clip space
+                    let scale: Transform3D<f32, UnknownUnit, UnknownUnit> =
+                        Transform3D::scale(width / 2.0, height / 2.0, 1.0);
+                    let transform = scale.then(to_clip).to_array();
+                    self.quad_shader.draw_layer(
+                        texture_id,
+                        texture_target,
+                        &transform,
+                        *tex_rect,
+                        &[],
+                        viewport,
+                    );
+                }
+                LayerInit::CylinderLayer {
+                    radius,
+                    central_angle,
+                    aspect_ratio,
+                    ..
+                } => {
+                    // The reprojection shaders map clip space back to the layer's space
+                    let transform = match to_clip.inverse() {
+                        Some(clip_to_layer) => clip_to_layer.to_array(),
+                        None => continue,
+                    };
+                    let height = radius * central_angle / aspect_ratio;
+                    self.cylinder_shader.draw_layer(
+                        texture_id,
+                        texture_target,
+                        &transform,
+                        *tex_rect,
+                        &[
+                            ("radius", radius),
+                            ("central_angle", central_angle),
+                            ("height", height),
+                        ],
+                        viewport,
+                    );
+                }
+                LayerInit::EquirectLayer {
+                    radius,
+                    central_horizontal_angle,
+                    upper_vertical_angle,
+                    lower_vertical_angle,
+                    ..
+                } => {
+                    let transform = match to_clip.inverse() {
+                        Some(clip_to_layer) => clip_to_layer.to_array(),
+                        None => continue,
+                    };
+                    self.equirect_shader.draw_layer(
+                        texture_id,
+                        texture_target,
+                        &transform,
+                        *tex_rect,
+                        &[
+                            ("radius", radius),
+                            ("central_horizontal_angle", central_horizontal_angle),
+                            ("upper_vertical_angle", upper_vertical_angle),
+                            ("lower_vertical_angle", lower_vertical_angle),
+                        ],
+                        viewport,
+                    );
+                }
+                LayerInit::CubeLayer { .. } => {
+                    let transform = match to_clip.inverse() {
+                        Some(clip_to_layer) => clip_to_layer.to_array(),
+                        None => continue,
+                    };
+                    self.cube_shader.draw_layer(
+                        texture_id,
+                        texture_target,
+                        &transform,
+                        *tex_rect,
+                        &[],
+                        viewport,
+                    );
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The native pose of a space's origin, for the spaces this device tracks
+    fn native_origin(&self, space: Space) -> Option<RigidTransform3D<f32, ApiSpace, Native>> {
+        let origin: RigidTransform3D<f32, ApiSpace, Native> = match space.base {
+            BaseSpace::Local => RigidTransform3D::identity(),
+            BaseSpace::Floor | BaseSpace::BoundedFloor => {
+                self.floor_transform()?.inverse().cast_unit()
+            }
+            BaseSpace::Viewer => self.viewer.cast_unit(),
+            BaseSpace::TargetRay(..) | BaseSpace::Grip(..) | BaseSpace::Joint(..) => return None,
+        };
+        Some(space.offset.then(&origin))
+    }
+
     fn layer_manager(&mut self) -> Result<&mut LayerManager, Error> {
         if let Some(ref mut manager) = self.layer_manager {
             return Ok(manager);
         }
         let swap_chains = self.swap_chains.clone();
+        let depth_swap_chains = self.depth_swap_chains.clone();
         let viewports = self.viewports();
         let layer_manager = self.grand_manager.create_layer_manager(move |_, _| {
-            Ok(SurfmanLayerManager::new(viewports, swap_chains))
+            Ok(SurfmanLayerManager::new(
+                viewports,
+                swap_chains,
+                Some(depth_swap_chains),
+                false,
+            ))
         })?;
         self.layer_manager = Some(layer_manager);
         Ok(self.layer_manager.as_mut().unwrap())
@@ -566,6 +948,8 @@ impl GlWindowDevice {
         View {
             transform: transform.inverse().then(&viewer),
             projection,
+            depth: None,
+            camera: None,
         }
     }
 
@@ -598,6 +982,17 @@ impl GlWindowDevice {
     }
 }
 
+/// The transform from a layer's space to clip space for a view
+fn layer_to_clip<Eye>(
+    view: &View<Eye>,
+    pose: RigidTransform3D<f32, ApiSpace, Native>,
+) -> Transform3D<f32, UnknownUnit, UnknownUnit> {
+    pose.to_transform()
+        .to_untyped()
+        .then(&view.transform.inverse().to_transform().to_untyped())
+        .then(&view.projection.to_untyped())
+}
+
 struct GlWindowShader {
     gl: Rc<Gl>,
     buffer: Option<gl::NativeBuffer>,
@@ -629,6 +1024,169 @@ const PASSTHROUGH_FRAGMENT_SHADER: &str = "
   }
 ";
 
+// Looks up the depth packed by the layer manager's `DepthPacker`, if the layer submitted
+// any, and linearizes it with the clip planes it was rendered with, so that layers are
+// compared by distance from the viewer
+macro_rules! layer_depth_shader {
+    () => {
+        "
+  uniform sampler2D depth;
+  uniform bool has_depth;
+  uniform float near;
+  uniform float far;
+  float layer_depth(vec2 coord) {
+    if (!has_depth) {
+      return gl_FragCoord.z;
+    }
+    float value = dot(texture(depth, coord).rgb, vec3(1.0, 1.0/255.0, 1.0/65025.0));
+    float z = value * 2.0 - 1.0;
+    float distance = 2.0 * near * far / (far + near - z * (far - near));
+    return clamp((distance - near) / (far - near), 0.0, 1.0);
+  }
+"
+    };
+}
+
+// Maps texture coordinates to the corner of each view that content scaled by the
+// viewport scale fills, where views are laid out in a grid of `view_size` cells
+macro_rules! viewport_scale_shader {
+    () => {
+        "
+  uniform vec2 view_size; // The size of each view, in texture coordinates
+  uniform float viewport_scale;
+  vec2 scaled_coord(vec2 coord) {
+    vec2 view = min(floor(coord / view_size), 1.0 / view_size - 1.0);
+    vec2 origin = view * view_size;
+    return origin + (coord - origin) * viewport_scale;
+  }
+"
+    };
+}
+
+// Draws a texture with its depth, if it has any
+const DEPTH_FRAGMENT_SHADER: &str = concat!(
+    "
+  #version 330 core
+  layout(location=0) out vec4 color;
+  uniform sampler2D image;
+  in vec2 vTexCoord;
+",
+    viewport_scale_shader!(),
+    layer_depth_shader!(),
+    "
+  void main() {
+    vec2 coord = scaled_coord(vTexCoord);
+    color = texture(image, coord);
+    gl_FragDepth = layer_depth(coord);
+  }
+"
+);
+
+const QUAD_VERTEX_SHADER: &str = "
+  #version 330 core
+  layout(location=0) in vec2 coord;
+  uniform mat4 transform;
+  uniform vec4 tex_rect; // The sub image, as (x, y, width, height) in texture coordinates
+  out vec2 vTexCoord;
+  void main(void) {
+    gl_Position = transform * vec4(coord, 0.0, 1.0);
+    vTexCoord = tex_rect.xy + (coord * 0.5 + 0.5) * tex_rect.zw;
+  }
+";
+
+// Casts a ray through each pixel, in the space of the layer
+const REPROJECT_VERTEX_SHADER: &str = "
+  #version 330 core
+  layout(location=0) in vec2 coord;
+  uniform mat4 transform; // From clip space to the layer's space
+  out vec4 near;
+  out vec4 far;
+  void main(void) {
+    gl_Position = vec4(coord, 0.0, 1.0);
+    near = transform * vec4(coord, -1.0, 1.0);
+    far = transform * vec4(coord, 1.0, 1.0);
+  }
+";
+
+const CYLINDER_FRAGMENT_SHADER: &str = "
+  #version 330 core
+  layout(location=0) out vec4 color;
+  uniform sampler2D image;
+  uniform vec4 tex_rect;
+  uniform float radius;
+  uniform float central_angle;
+  uniform float height;
+  in vec4 near;
+  in vec4 far;
+  void main() {
+    vec3 origin = near.xyz / near.w;
+    vec3 direction = normalize(far.xyz / far.w - origin);
+    // Intersect the ray with the inside of the cylinder x^2 + z^2 = radius^2
+    float a = dot(direction.xz, direction.xz);
+    float b = 2.0 * dot(origin.xz, direction.xz);
+    float c = dot(origin.xz, origin.xz) - radius * radius;
+    float discriminant = b * b - 4.0 * a * c;
+    if (a == 0.0 || discriminant < 0.0) {
+      discard;
+    }
+    float t = (-b + sqrt(discriminant)) / (2.0 * a);
+    if (t < 0.0) {
+      discard;
+    }
+    vec3 point = origin + t * direction;
+    vec2 uv = vec2(
+      atan(point.x, -point.z) / central_angle + 0.5,
+      point.y / height + 0.5
+    );
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+      discard;
+    }
+    color = texture(image, tex_rect.xy + uv * tex_rect.zw);
+  }
+";
+
+const EQUIRECT_FRAGMENT_SHADER: &str = "
+  #version 330 core
+  layout(location=0) out vec4 color;
+  uniform sampler2D image;
+  uniform vec4 tex_rect;
+  uniform float radius; // Zero for an infinite sphere
+  uniform float central_horizontal_angle;
+  uniform float upper_vertical_angle;
+  uniform float lower_vertical_angle;
+  in vec4 near;
+  in vec4 far;
+  void main() {
+    vec3 origin = near.xyz / near.w;
+    vec3 direction = normalize(far.xyz / far.w - origin);
+    vec3 point = direction;
+    if (radius > 0.0) {
+      // Intersect the ray with the inside of the sphere
+      float b = dot(origin, direction);
+      float c = dot(origin, origin) - radius * radius;
+      float discriminant = b * b - c;
+      if (discriminant < 0.0) {
+        discard;
+      }
+      float t = -b + sqrt(discriminant);
+      if (t < 0.0) {
+        discard;
+      }
+      point = normalize(origin + t * direction);
+    }
+    float lon = atan(point.x, -point.z);
+    float lat = asin(clamp(point.y, -1.0, 1.0));
+    vec2 uv = vec2(
+      lon / central_horizontal_angle + 0.5,
+      (lat - lower_vertical_angle) / (upper_vertical_angle - lower_vertical_angle)
+    );
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+      discard;
+    }
+    color = texture(image, tex_rect.xy + uv * tex_rect.zw);
+  }
+";
+
 const ANAGLYPH_VERTEX_SHADER: &str = "
   #version 330 core
   layout(location=0) in vec2 coord;
@@ -643,21 +1201,30 @@ const ANAGLYPH_VERTEX_SHADER: &str = "
   }
 ";
 
-const ANAGLYPH_RED_CYAN_FRAGMENT_SHADER: &str = "
+const ANAGLYPH_RED_CYAN_FRAGMENT_SHADER: &str = concat!(
+    "
   #version 330 core
   layout(location=0) out vec4 color;
   uniform sampler2D image;
   in vec2 left_coord;
   in vec2 right_coord;
+",
+    viewport_scale_shader!(),
+    layer_depth_shader!(),
+    "
   void main() {
-    vec4 left_color = texture(image, left_coord);
-    vec4 right_color = texture(image, right_coord);
+    vec2 left = scaled_coord(left_coord);
+    vec2 right = scaled_coord(right_coord);
+    vec4 left_color = texture(image, left);
+    vec4 right_color = texture(image, right);
     float red = left_color.x;
     float green = right_color.y;
     float blue = right_color.z;
     color = vec4(red, green, blue, 1.0);
+    gl_FragDepth = min(layer_depth(left), layer_depth(right));
   }
-";
+"
+);
 
 const SPHERICAL_VERTEX_SHADER: &str = "
   #version 330 core
@@ -670,17 +1237,12 @@ const SPHERICAL_VERTEX_SHADER: &str = "
   }
 ";
 
-const SPHERICAL_FRAGMENT_SHADER: &str = "
-  #version 330 core
-  layout(location=0) out vec4 color;
-  uniform sampler2D image;
-  in vec2 lon_lat;
-  void main() {
-    vec3 direction = vec3(
-      sin(lon_lat.x)*cos(lon_lat.y),
-      sin(lon_lat.y),
-      cos(lon_lat.x)*cos(lon_lat.y)
-    );
+// Looks up a direction in six cube faces laid out as in `cube_face_rects`,
+// where the direction is left-handed, with +Z ahead
+macro_rules! cube_face_coord_shader {
+    () => {
+        "
+  vec2 cube_face_coord(vec3 direction) {
     vec2 vTexCoord;
     if ((direction.y > abs(direction.x)) && (direction.y > abs(direction.z))) {
       // Looking up
@@ -707,9 +1269,55 @@ const SPHERICAL_FRAGMENT_SHADER: &str = "
       vTexCoord.x = direction.x / (direction.z*6.0) + 3.0/6.0;
       vTexCoord.y = direction.y / (direction.z*4.0) + 3.0/4.0;
     }
-    color = texture(image, vTexCoord);
+    return vTexCoord;
   }
-";
+"
+    };
+}
+
+const SPHERICAL_FRAGMENT_SHADER: &str = concat!(
+    "
+  #version 330 core
+  layout(location=0) out vec4 color;
+  uniform sampler2D image;
+  in vec2 lon_lat;
+",
+    cube_face_coord_shader!(),
+    viewport_scale_shader!(),
+    layer_depth_shader!(),
+    "
+  void main() {
+    vec3 direction = vec3(
+      sin(lon_lat.x)*cos(lon_lat.y),
+      sin(lon_lat.y),
+      cos(lon_lat.x)*cos(lon_lat.y)
+    );
+    vec2 coord = scaled_coord(cube_face_coord(direction));
+    color = texture(image, coord);
+    gl_FragDepth = layer_depth(coord);
+  }
+"
+);
+
+const CUBE_FRAGMENT_SHADER: &str = concat!(
+    "
+  #version 330 core
+  layout(location=0) out vec4 color;
+  uniform sampler2D image;
+  uniform vec4 tex_rect;
+  in vec4 near;
+  in vec4 far;
+",
+    cube_face_coord_shader!(),
+    "
+  void main() {
+    // The cube is infinitely far away, so only the ray's direction matters
+    vec3 direction = normalize(far.xyz / far.w - near.xyz / near.w);
+    direction.z = -direction.z;
+    color = texture(image, tex_rect.xy + cube_face_coord(direction) * tex_rect.zw);
+  }
+"
+);
 
 impl GlWindowShader {
     fn new(gl: Rc<Gl>, mode: GlWindowMode) -> Option<GlWindowShader> {
@@ -719,14 +1327,89 @@ impl GlWindowShader {
                 return None;
             }
             GlWindowMode::StereoLeftRight | GlWindowMode::Cubemap => {
-                (PASSTHROUGH_VERTEX_SHADER, PASSTHROUGH_FRAGMENT_SHADER)
+                (PASSTHROUGH_VERTEX_SHADER, DEPTH_FRAGMENT_SHADER)
             }
             GlWindowMode::StereoRedCyan => {
                 (ANAGLYPH_VERTEX_SHADER, ANAGLYPH_RED_CYAN_FRAGMENT_SHADER)
             }
             GlWindowMode::Spherical => (SPHERICAL_VERTEX_SHADER, SPHERICAL_FRAGMENT_SHADER),
         };
+        Some(GlWindowShader::compile(
+            gl,
+            mode,
+            vertex_source,
+            fragment_source,
+        ))
+    }
+
+    /// A shader that draws a texture over the whole window, used for overlays.
+    /// Its mode is `Blit` since it needs no mode-specific uniforms.
+    fn overlay(gl: Rc<Gl>) -> GlWindowShader {
+        GlWindowShader::compile(
+            gl,
+            GlWindowMode::Blit,
+            PASSTHROUGH_VERTEX_SHADER,
+            PASSTHROUGH_FRAGMENT_SHADER,
+        )
+    }
+
+    /// A shader that draws a texture onto a quad in 3D space, used for quad layers
+    fn quad(gl: Rc<Gl>) -> GlWindowShader {
+        GlWindowShader::compile(
+            gl,
+            GlWindowMode::Blit,
+            QUAD_VERTEX_SHADER,
+            PASSTHROUGH_FRAGMENT_SHADER,
+        )
+    }
 
+    /// A shader that reprojects a texture onto a cylinder, used for cylinder layers
+    fn cylinder(gl: Rc<Gl>) -> GlWindowShader {
+        GlWindowShader::compile(
+            gl,
+            GlWindowMode::Blit,
+            REPROJECT_VERTEX_SHADER,
+            CYLINDER_FRAGMENT_SHADER,
+        )
+    }
+
+    /// A shader that reprojects an equirectangular texture onto a sphere, used for equirect layers
+    fn equirect(gl: Rc<Gl>) -> GlWindowShader {
+        GlWindowShader::compile(
+            gl,
+            GlWindowMode::Blit,
+            REPROJECT_VERTEX_SHADER,
+            EQUIRECT_FRAGMENT_SHADER,
+        )
+    }
+
+    /// A shader that draws six faces of a cube around the viewer, used for cube layers
+    fn cube(gl: Rc<Gl>) -> GlWindowShader {
+        GlWindowShader::compile(
+            gl,
+            GlWindowMode::Blit,
+            REPROJECT_VERTEX_SHADER,
+            CUBE_FRAGMENT_SHADER,
+        )
+    }
+
+    /// A shader that draws a texture over the whole window using its packed depth,
+    /// used for layers that submit depth in `Blit` mode
+    fn depth(gl: Rc<Gl>) -> GlWindowShader {
+        GlWindowShader::compile(
+            gl,
+            GlWindowMode::Blit,
+            PASSTHROUGH_VERTEX_SHADER,
+            DEPTH_FRAGMENT_SHADER,
+        )
+    }
+
+    fn compile(
+        gl: Rc<Gl>,
+        mode: GlWindowMode,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> GlWindowShader {
         // TODO: work out why shaders don't work on macos
         if cfg!(target_os = "macos") {
             log::warn!("XR shaders may not render on MacOS.");
@@ -795,13 +1478,13 @@ impl GlWindowShader {
             debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
 
             // And we're done
-            Some(GlWindowShader {
+            GlWindowShader {
                 gl,
                 buffer,
                 vao,
                 program,
                 mode,
-            })
+            }
         }
     }
 
@@ -812,6 +1495,8 @@ impl GlWindowShader {
         texture_size: Size2D<i32, UnknownUnit>,
         viewport_size: Size2D<i32, Viewport>,
         window_size: Size2D<i32, Viewport>,
+        viewport_scale: f32,
+        depth: Option<(gl::NativeTexture, ClipPlanes)>,
     ) {
         unsafe {
             self.gl.use_program(Some(self.program));
@@ -828,8 +1513,40 @@ impl GlWindowShader {
 
             debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
 
+            let view_size_location = self.gl.get_uniform_location(self.program, "view_size");
+            self.gl.uniform_2_f32(
+                view_size_location.as_ref(),
+                viewport_size.width as f32 / texture_size.width as f32,
+                viewport_size.height as f32 / texture_size.height as f32,
+            );
+            let viewport_scale_location =
+                self.gl.get_uniform_location(self.program, "viewport_scale");
+            self.gl
+                .uniform_1_f32(viewport_scale_location.as_ref(), viewport_scale);
+
+            // Layers with depth are composited by depth, and the rest in painter's order
+            let has_depth_location = self.gl.get_uniform_location(self.program, "has_depth");
+            self.gl
+                .uniform_1_i32(has_depth_location.as_ref(), depth.is_some() as i32);
+            if let Some((depth_texture_id, clip_planes)) = depth {
+                self.gl.active_texture(gl::TEXTURE1);
+                self.gl.bind_texture(texture_target, Some(depth_texture_id));
+                let depth_location = self.gl.get_uniform_location(self.program, "depth");
+                self.gl.uniform_1_i32(depth_location.as_ref(), 1);
+                let near_location = self.gl.get_uniform_location(self.program, "near");
+                self.gl
+                    .uniform_1_f32(near_location.as_ref(), clip_planes.near);
+                let far_location = self.gl.get_uniform_location(self.program, "far");
+                self.gl
+                    .uniform_1_f32(far_location.as_ref(), clip_planes.far);
+                self.gl.enable(gl::DEPTH_TEST);
+                self.gl.depth_func(gl::LEQUAL);
+            }
+
             self.gl.active_texture(gl::TEXTURE0);
             self.gl.bind_texture(texture_target, texture_id);
+            let image_location = self.gl.get_uniform_location(self.program, "image");
+            self.gl.uniform_1_i32(image_location.as_ref(), 0);
 
             match self.mode {
                 GlWindowMode::StereoRedCyan => {
@@ -848,6 +1565,61 @@ impl GlWindowShader {
 
             self.gl
                 .viewport(0, 0, window_size.width, window_size.height);
+            self.gl
+                .draw_arrays(gl::TRIANGLE_STRIP, 0, VERTICES.len() as i32);
+            if depth.is_some() {
+                self.gl.disable(gl::DEPTH_TEST);
+            }
+            self.gl.disable_vertex_attrib_array(VERTEX_ATTRIBUTE);
+            debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
+        }
+    }
+
+    /// Draw a world layer into a viewport, setting the `transform` and `tex_rect` uniforms
+    /// along with any layer-specific float uniforms
+    fn draw_layer(
+        &self,
+        texture_id: Option<gl::NativeTexture>,
+        texture_target: u32,
+        transform: &[f32; 16],
+        tex_rect: [f32; 4],
+        params: &[(&str, f32)],
+        viewport: Rect<i32, Viewport>,
+    ) {
+        unsafe {
+            self.gl.use_program(Some(self.program));
+
+            self.gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTE);
+            self.gl.vertex_attrib_pointer_f32(
+                VERTEX_ATTRIBUTE,
+                VERTICES[0].len() as i32,
+                gl::FLOAT,
+                false,
+                0,
+                0,
+            );
+
+            self.gl.active_texture(gl::TEXTURE0);
+            self.gl.bind_texture(texture_target, texture_id);
+
+            let transform_location = self.gl.get_uniform_location(self.program, "transform");
+            self.gl
+                .uniform_matrix_4_f32_slice(transform_location.as_ref(), false, transform);
+            let tex_rect_location = self.gl.get_uniform_location(self.program, "tex_rect");
+            let [x, y, width, height] = tex_rect;
+            self.gl
+                .uniform_4_f32(tex_rect_location.as_ref(), x, y, width, height);
+            for &(name, value) in params {
+                let location = self.gl.get_uniform_location(self.program, name);
+                self.gl.uniform_1_f32(location.as_ref(), value);
+            }
+
+            self.gl.viewport(
+                viewport.origin.x,
+                viewport.origin.y,
+                viewport.size.width,
+                viewport.size.height,
+            );
             self.gl
                 .draw_arrays(gl::TRIANGLE_STRIP, 0, VERTICES.len() as i32);
             self.gl.disable_vertex_attrib_array(VERTEX_ATTRIBUTE);
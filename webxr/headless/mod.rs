@@ -2,29 +2,60 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+#[cfg(feature = "recording")]
+mod recording;
+mod reproject;
+
+#[cfg(feature = "recording")]
+use self::recording::Recorder;
+use self::reproject::Reprojection;
 use crate::SurfmanGL;
 use crate::SurfmanLayerManager;
-use euclid::{Point2D, RigidTransform3D};
+use euclid::{Point2D, Point3D, Rect, RigidTransform3D, Transform3D};
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+#[cfg(feature = "recording")]
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use surfman::chains::SwapChains;
-use webxr_api::util::{self, ClipPlanes, HitTestList};
+use webxr_api::util::{self, ClipPlanes, Clock, HitTestList, MeshList};
 use webxr_api::{
-    ApiSpace, BaseSpace, ContextId, DeviceAPI, DiscoveryAPI, Error, Event, EventBuffer, Floor,
-    Frame, FrameUpdateEvent, HitTestId, HitTestResult, HitTestSource, Input, InputFrame, InputId,
-    InputSource, LayerGrandManager, LayerId, LayerInit, LayerManager, MockButton, MockDeviceInit,
-    MockDeviceMsg, MockDiscoveryAPI, MockInputMsg, MockViewInit, MockViewsInit, MockWorld, Native,
-    Quitter, Ray, Receiver, SelectEvent, SelectKind, Sender, Session, SessionBuilder, SessionInit,
-    SessionMode, Space, SubImages, View, Viewer, ViewerPose, Viewports, Views,
+    AnchorId, AnchorPose, AnchorSpace, ApiSpace, BaseSpace, Bvh, Camera, CameraImage,
+    CameraIntrinsics, ContextId, DepthData, DepthDataFormat, DepthInformation, DepthSensing,
+    DepthUsage, DetectedMesh, DetectedPlane, DeviceAPI, DiscoveryAPI, Display, DomOverlayType,
+    EntityType, EntityTypes, Error, Event, EventBuffer, Floor, Frame, FrameUpdateEvent, HitTestId,
+    HitTestResult, HitTestSource, Input, InputFrame, InputId, InputSource, LayerGrandManager,
+    LayerId, LayerImage, LayerInit, LayerManager, LightEstimate, MeshId, MockButton,
+    MockDeviceInit, MockDeviceMsg, MockDiscoveryAPI, MockInputMsg, MockViewInit, MockViewsInit,
+    MockWorld, Native, PlaneId, Quitter, Ray, Receiver, SelectEvent, SelectKind, Sender, Session,
+    SessionBuilder, SessionInit, SessionMode, Space, SubImages, TargetRayMode,
+    TransientHitTestResult, TransientHitTestSource, View, Viewer, ViewerPose, Viewport, Viewports,
+    Views,
 };
 
-pub struct HeadlessMockDiscovery {}
+// Depth is synthesized at a fraction of the viewport resolution, since it is raycast per pixel
+const DEPTH_DOWNSCALE: i32 = 4;
+// The frame rate of devices that don't support any other, in frames per second
+const DEFAULT_FRAME_RATE: f32 = 50.0;
+const DEPTH_USAGES: &[DepthUsage] = &[DepthUsage::CpuOptimized];
+const DEPTH_DATA_FORMATS: &[DepthDataFormat] =
+    &[DepthDataFormat::LuminanceAlpha, DepthDataFormat::Float32];
+
+pub struct HeadlessMockDiscovery {
+    /// The directory that sessions are recorded into
+    #[cfg(feature = "recording")]
+    recording: Option<PathBuf>,
+}
 
 struct HeadlessDiscovery {
     data: Arc<Mutex<HeadlessDeviceData>>,
     supports_vr: bool,
     supports_inline: bool,
     supports_ar: bool,
+    #[cfg(feature = "recording")]
+    recording: Option<PathBuf>,
 }
 
 struct InputInfo {
@@ -36,13 +67,36 @@ struct InputInfo {
     buttons: Vec<MockButton>,
 }
 
+/// The most recently uploaded camera image, with the generation and context it was uploaded for
+#[derive(Clone, Copy)]
+struct CameraTexture {
+    generation: u32,
+    context_id: ContextId,
+    width: u32,
+    height: u32,
+    texture: u32,
+}
+
 struct HeadlessDevice {
     data: Arc<Mutex<HeadlessDeviceData>>,
     id: u32,
     hit_tests: HitTestList,
+    meshes: MeshList,
     granted_features: Vec<String>,
+    depth_sensing: Option<DepthSensing>,
+    camera: Option<CameraTexture>,
     grand_manager: LayerGrandManager<SurfmanGL>,
     layer_manager: Option<LayerManager>,
+    layer_inits: HashMap<LayerId, LayerInit>,
+    /// How layers that aren't drawn per view are drawn into the views of the current frame
+    reprojections: HashMap<LayerId, Reprojection>,
+    #[cfg(feature = "recording")]
+    recorder: Option<Recorder>,
+    frame_rate: f32,
+    /// Whether the frame rate needs to be reported in the next frame
+    frame_rate_changed: bool,
+    /// When the previous frame ended, so frames can be paced to the frame rate
+    last_frame_end: Option<Instant>,
 }
 
 struct PerSessionData {
@@ -52,6 +106,9 @@ struct PerSessionData {
     quitter: Option<Quitter>,
     events: EventBuffer,
     needs_vp_update: bool,
+    anchors: Vec<(AnchorId, RigidTransform3D<f32, AnchorSpace, Native>)>,
+    next_anchor_id: u32,
+    light_probe_requested: bool,
 }
 
 struct HeadlessDeviceData {
@@ -63,9 +120,24 @@ struct HeadlessDeviceData {
     inputs: Vec<InputInfo>,
     sessions: Vec<PerSessionData>,
     disconnected: bool,
-    world: Option<MockWorld>,
+    /// The mock world's triangles, tagged with their region index and type
+    world: Bvh<(u32, EntityType)>,
+    planes: Vec<DetectedPlane>,
+    meshes: Vec<DetectedMesh>,
     next_id: u32,
     bounds_geometry: Vec<Point2D<f32, Floor>>,
+    light_estimate: Option<LightEstimate>,
+    camera_image: Option<CameraImage>,
+    /// Incremented whenever the camera image changes, so sessions know to upload it again
+    camera_image_generation: u32,
+    /// The amount of foveation applied to each layer
+    layer_foveations: HashMap<LayerId, f32>,
+    /// Requests for the color textures submitted in the next frame
+    capture_requests: Vec<Sender<Vec<(LayerId, LayerImage)>>>,
+    clock: Clock,
+    supported_frame_rates: Vec<f32>,
+    /// Updates that are applied at the start of the first frame at or after their time
+    scheduled: VecDeque<(Duration, MockDeviceMsg)>,
 }
 
 impl MockDiscoveryAPI<SurfmanGL> for HeadlessMockDiscovery {
@@ -74,34 +146,7 @@ impl MockDiscoveryAPI<SurfmanGL> for HeadlessMockDiscovery {
         init: MockDeviceInit,
         receiver: Receiver<MockDeviceMsg>,
     ) -> Result<Box<dyn DiscoveryAPI<SurfmanGL>>, Error> {
-        let viewer_origin = init.viewer_origin.clone();
-        let floor_transform = init.floor_origin.map(|f| f.inverse());
-        let views = init.views.clone();
-        let data = HeadlessDeviceData {
-            floor_transform,
-            viewer_origin,
-            supported_features: init.supported_features,
-            views,
-            needs_floor_update: false,
-            inputs: vec![],
-            sessions: vec![],
-            disconnected: false,
-            world: init.world,
-            next_id: 0,
-            bounds_geometry: vec![],
-        };
-        let data = Arc::new(Mutex::new(data));
-        let data_ = data.clone();
-
-        thread::spawn(move || {
-            run_loop(receiver, data_);
-        });
-        Ok(Box::new(HeadlessDiscovery {
-            data,
-            supports_vr: init.supports_vr,
-            supports_inline: init.supports_inline,
-            supports_ar: init.supports_ar,
-        }))
+        self.simulate_device_playback(init, receiver, vec![])
     }
 }
 
@@ -134,20 +179,60 @@ impl DiscoveryAPI<SurfmanGL> for HeadlessDiscovery {
             quitter: Default::default(),
             events: Default::default(),
             needs_vp_update: false,
+            anchors: vec![],
+            next_anchor_id: 0,
+            light_probe_requested: false,
         };
         d.sessions.push(per_session);
 
-        let granted_features = init.validate(mode, &d.supported_features)?;
+        let mut granted_features = init.validate(mode, &d.supported_features)?;
+        let mut depth_sensing = None;
+        if granted_features.iter().any(|f| f == "depth-sensing") {
+            depth_sensing = init
+                .depth_sensing
+                .as_ref()
+                .and_then(|depth| depth.select(DEPTH_USAGES, DEPTH_DATA_FORMATS));
+            if depth_sensing.is_none() {
+                // https://immersive-web.github.io/depth-sensing/#depth-sensing-feature
+                if init.required_features.iter().any(|f| f == "depth-sensing") {
+                    return Err(Error::UnsupportedFeature("depth-sensing".into()));
+                }
+                granted_features.retain(|f| f != "depth-sensing");
+            }
+        }
         let layer_manager = None;
+        let frame_rate = d
+            .supported_frame_rates
+            .iter()
+            .copied()
+            .max_by(|a, b| a.total_cmp(b))
+            .unwrap_or(DEFAULT_FRAME_RATE);
+        // Devices without a choice of frame rates don't report one
+        let frame_rate_changed = !d.supported_frame_rates.is_empty();
+        #[cfg(feature = "recording")]
+        let recorder = self
+            .recording
+            .as_ref()
+            .map(|dir| Recorder::new(dir.join(format!("session-{}", id))));
         drop(d);
         xr.spawn(move |grand_manager| {
             Ok(HeadlessDevice {
                 data,
                 id,
                 granted_features,
+                depth_sensing,
+                camera: None,
                 hit_tests: HitTestList::default(),
+                meshes: MeshList::default(),
                 grand_manager,
                 layer_manager,
+                layer_inits: HashMap::new(),
+                reprojections: HashMap::new(),
+                #[cfg(feature = "recording")]
+                recorder,
+                frame_rate,
+                frame_rate_changed,
+                last_frame_end: None,
             })
         })
     }
@@ -178,6 +263,24 @@ fn view<Eye>(
     View {
         transform: init.transform.inverse().then(&viewer),
         projection,
+        depth: None,
+        camera: None,
+    }
+}
+
+impl CameraTexture {
+    /// The mock camera is aligned with each view, so its intrinsics follow from the view's projection
+    fn for_view<Eye>(&self, view: &View<Eye>) -> Camera {
+        Camera {
+            width: self.width,
+            height: self.height,
+            texture: self.texture,
+            intrinsics: CameraIntrinsics::from_projection(
+                &view.projection,
+                self.width,
+                self.height,
+            ),
+        }
     }
 }
 
@@ -193,6 +296,38 @@ impl HeadlessDevice {
             .unwrap())
     }
 
+    /// Upload the mock camera image into the content's GL context, if it has changed
+    fn camera(&mut self, layers: &[(ContextId, LayerId)]) -> Option<CameraTexture> {
+        if !self.granted_features.iter().any(|f| f == "camera-access") {
+            return None;
+        }
+        let &(context_id, _) = layers.first()?;
+        let data = self.data.lock().unwrap();
+        let generation = data.camera_image_generation;
+        match self.camera {
+            Some(camera) if camera.generation == generation && camera.context_id == context_id => {
+                return Some(camera)
+            }
+            _ => (),
+        }
+        let image = data.camera_image.clone()?;
+        drop(data);
+        let texture = self
+            .layer_manager()
+            .ok()?
+            .upload_camera_image(context_id, &image)
+            .ok()?;
+        let camera = CameraTexture {
+            generation,
+            context_id,
+            width: image.width,
+            height: image.height,
+            texture,
+        };
+        self.camera = Some(camera);
+        Some(camera)
+    }
+
     fn layer_manager(&mut self) -> Result<&mut LayerManager, Error> {
         if let Some(ref mut manager) = self.layer_manager {
             return Ok(manager);
@@ -200,7 +335,12 @@ impl HeadlessDevice {
         let swap_chains = SwapChains::new();
         let viewports = self.viewports();
         let layer_manager = self.grand_manager.create_layer_manager(move |_, _| {
-            Ok(SurfmanLayerManager::new(viewports, swap_chains))
+            Ok(SurfmanLayerManager::new(
+                viewports,
+                swap_chains,
+                None,
+                false,
+            ))
         })?;
         self.layer_manager = Some(layer_manager);
         Ok(self.layer_manager.as_mut().unwrap())
@@ -219,10 +359,23 @@ impl DeviceAPI for HeadlessDevice {
     }
 
     fn create_layer(&mut self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
-        self.layer_manager()?.create_layer(context_id, init)
+        let layer_id = self.layer_manager()?.create_layer(context_id, init)?;
+        self.layer_inits.insert(layer_id, init);
+        // The requested level is clamped, as it would be by `Session::set_layer_foveation`
+        let foveation = init.fixed_foveation().max(0.0).min(1.0);
+        let mut data = self.data.lock().unwrap();
+        data.layer_foveations.insert(layer_id, foveation);
+        Ok(layer_id)
     }
 
     fn destroy_layer(&mut self, context_id: ContextId, layer_id: LayerId) {
+        self.data.lock().unwrap().layer_foveations.remove(&layer_id);
+        self.layer_inits.remove(&layer_id);
+        // The camera texture is deleted along with the context's last layer,
+        // so upload the image again rather than risk handing out a stale texture
+        if matches!(self.camera, Some(camera) if camera.context_id == context_id) {
+            self.camera = None;
+        }
         self.layer_manager()
             .unwrap()
             .destroy_layer(context_id, layer_id)
@@ -230,11 +383,49 @@ impl DeviceAPI for HeadlessDevice {
 
     fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
         let sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
+        let camera = self.camera(layers);
         let mut data = self.data.lock().unwrap();
+        data.apply_scheduled();
         let mut frame = data.get_frame(
             data.sessions.iter().find(|s| s.id == self.id).unwrap(),
             sub_images,
         );
+        if let (Some(depth_sensing), Some(pose)) = (self.depth_sensing, frame.pose.as_mut()) {
+            let mode = data.sessions.iter().find(|s| s.id == self.id).unwrap().mode;
+            let viewports = data.viewports(mode);
+            data.add_depth(&mut pose.views, &viewports, depth_sensing.data_format);
+        }
+        self.reprojections.clear();
+        if let Some(ref pose) = frame.pose {
+            // Cylinder and equirect layers are read back as they would be seen from each view
+            let mode = data.sessions.iter().find(|s| s.id == self.id).unwrap().mode;
+            let viewports = data.viewports(mode);
+            for &(_, layer_id) in layers {
+                let init = match self.layer_inits.get(&layer_id) {
+                    Some(init) => init,
+                    None => continue,
+                };
+                let layer_pose = init.world_pose().and_then(|(space, transform)| {
+                    Some(transform.then(&data.native_origin(space)?))
+                });
+                let reprojection = layer_pose.and_then(|layer_pose| {
+                    Reprojection::new(init, layer_pose, &pose.views, &viewports)
+                });
+                if let Some(reprojection) = reprojection {
+                    self.reprojections.insert(layer_id, reprojection);
+                }
+            }
+        }
+        if let (Some(camera), Some(pose)) = (camera, frame.pose.as_mut()) {
+            match pose.views {
+                Views::Mono(ref mut one) => one.camera = Some(camera.for_view(one)),
+                Views::Stereo(ref mut one, ref mut two) => {
+                    one.camera = Some(camera.for_view(one));
+                    two.camera = Some(camera.for_view(two));
+                }
+                _ => (),
+            }
+        }
         let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
         if per_session.needs_vp_update {
             per_session.needs_vp_update = false;
@@ -244,37 +435,123 @@ impl DeviceAPI for HeadlessDevice {
         }
         let events = self.hit_tests.commit_tests();
         frame.events = events;
+        if self.frame_rate_changed {
+            self.frame_rate_changed = false;
+            frame
+                .events
+                .push(FrameUpdateEvent::UpdateFrameRate(self.frame_rate));
+        }
 
-        if let Some(ref world) = data.world {
-            for source in self.hit_tests.tests() {
-                let ray = data.native_ray(source.ray, source.space);
-                let ray = if let Some(ray) = ray { ray } else { break };
-                let hits = world
-                    .regions
-                    .iter()
-                    .filter(|region| source.types.is_type(region.ty))
-                    .flat_map(|region| &region.faces)
-                    .filter_map(|triangle| triangle.intersect(ray))
-                    .map(|space| HitTestResult {
-                        space,
+        for source in self.hit_tests.tests() {
+            let ray = data.native_ray(source.ray, source.space);
+            let ray = if let Some(ray) = ray { ray } else { break };
+            let hits = data.hit_test(source.id, ray, source.types);
+            frame.hit_test_results.extend(hits);
+        }
+
+        for source in self.hit_tests.transient_tests() {
+            let inputs = data.inputs.iter().filter(|i| {
+                i.active
+                    && matches!(i.source.target_ray_mode, TargetRayMode::TransientPointer)
+                    && i.source.profiles.contains(&source.profile)
+            });
+            for input in inputs {
+                let space = Space {
+                    base: BaseSpace::TargetRay(input.source.id),
+                    offset: RigidTransform3D::identity(),
+                };
+                let ray = match data.native_ray(source.ray, space) {
+                    Some(ray) => ray,
+                    None => continue,
+                };
+                let results = data.hit_test(source.id, ray, source.types);
+                frame
+                    .transient_hit_test_results
+                    .push(TransientHitTestResult {
                         id: source.id,
+                        input: input.source.id,
+                        results,
                     });
-                frame.hit_test_results.extend(hits);
             }
         }
 
+        if self.granted_features.iter().any(|f| f == "plane-detection") {
+            frame.detected_planes = data.planes.clone();
+        }
+
+        if self.granted_features.iter().any(|f| f == "mesh-detection") {
+            frame.mesh_updates = self.meshes.update(&data.meshes);
+        }
+
         if data.needs_floor_update {
             frame.events.push(FrameUpdateEvent::UpdateFloorTransform(
                 data.floor_transform.clone(),
             ));
             data.needs_floor_update = false;
         }
+        drop(data);
+
+        #[cfg(feature = "recording")]
+        if self.recorder.is_some() {
+            let viewports = self.viewports();
+            let reprojections = &self.reprojections;
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.begin_frame(&frame, &viewports, |layer_id| {
+                    reprojections.contains_key(&layer_id)
+                });
+            }
+        }
         Some(frame)
     }
 
     fn end_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) {
-        let _ = self.layer_manager().unwrap().end_frame(layers);
-        thread::sleep(std::time::Duration::from_millis(20));
+        // Submitted textures are only read back when something wants to see them
+        let capture_requests = mem::take(&mut self.data.lock().unwrap().capture_requests);
+        let read_back = !capture_requests.is_empty();
+        #[cfg(feature = "recording")]
+        let read_back = read_back || self.recorder.is_some();
+        let layer_manager = self.layer_manager().unwrap();
+        layer_manager.set_read_back(read_back);
+        let _ = layer_manager.end_frame(layers);
+        if read_back {
+            let images = layers
+                .iter()
+                .filter_map(|&(context_id, layer_id)| {
+                    let image = layer_manager.read_pixels(context_id, layer_id).ok()?;
+                    Some((layer_id, image))
+                })
+                .collect::<Vec<_>>();
+            let images = images
+                .into_iter()
+                .map(
+                    |(layer_id, image)| match self.reprojections.get(&layer_id) {
+                        Some(reprojection) => (layer_id, reprojection.reproject(&image)),
+                        None => (layer_id, image),
+                    },
+                )
+                .collect::<Vec<_>>();
+            #[cfg(feature = "recording")]
+            if let Some(ref mut recorder) = self.recorder {
+                if let Err(err) = recorder.end_frame(&images) {
+                    log::warn!("Failed to record frame: {:?}", err);
+                }
+            }
+            for sender in capture_requests {
+                let _ = sender.send(images.clone());
+            }
+        }
+        // A virtual clock moves on by a frame, and a real one is waited on
+        let frame_time = Duration::from_secs_f32(1.0 / self.frame_rate);
+        let mut data = self.data.lock().unwrap();
+        if data.clock.is_virtual() {
+            data.clock.advance(frame_time);
+        } else {
+            drop(data);
+            if let Some(last_frame_end) = self.last_frame_end {
+                thread::sleep(frame_time.saturating_sub(last_frame_end.elapsed()));
+            }
+            self.last_frame_end = Some(Instant::now());
+        }
     }
 
     fn initial_inputs(&self) -> Vec<InputSource> {
@@ -301,14 +578,117 @@ impl DeviceAPI for HeadlessDevice {
         &self.granted_features
     }
 
+    fn depth_sensing(&self) -> Option<DepthSensing> {
+        self.depth_sensing
+    }
+
+    fn dom_overlay_type(&self) -> Option<DomOverlayType> {
+        if self.granted_features.iter().any(|f| f == "dom-overlay") {
+            Some(DomOverlayType::Screen)
+        } else {
+            None
+        }
+    }
+
     fn request_hit_test(&mut self, source: HitTestSource) {
         self.hit_tests.request_hit_test(source)
     }
 
+    fn request_transient_hit_test(&mut self, source: TransientHitTestSource) {
+        self.hit_tests.request_transient_hit_test(source)
+    }
+
     fn cancel_hit_test(&mut self, id: HitTestId) {
         self.hit_tests.cancel_hit_test(id)
     }
 
+    fn create_anchor(
+        &mut self,
+        space: Space,
+        pose: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+    ) -> Result<AnchorId, Error> {
+        // Anchors are fixed in native space at the moment they are created,
+        // so later changes to the floor origin or viewer are observed as the
+        // anchor moving relative to those spaces.
+        let native = self
+            .data
+            .lock()
+            .unwrap()
+            .native_origin(space)
+            .ok_or(Error::BackendSpecific("Space is not tracked".into()))?;
+        self.create_native_anchor(pose.then(&native).cast_unit())
+    }
+
+    fn create_native_anchor(
+        &mut self,
+        pose: RigidTransform3D<f32, AnchorSpace, Native>,
+    ) -> Result<AnchorId, Error> {
+        if !self.granted_features.iter().any(|f| f == "anchors") {
+            return Err(Error::UnsupportedFeature("anchors".into()));
+        }
+        Ok(self.with_per_session(|s| {
+            let id = AnchorId(s.next_anchor_id);
+            s.next_anchor_id += 1;
+            s.anchors.push((id, pose));
+            id
+        }))
+    }
+
+    fn delete_anchor(&mut self, id: AnchorId) {
+        self.with_per_session(|s| s.anchors.retain(|&(other_id, _)| other_id != id))
+    }
+
+    fn request_light_probe(&mut self) -> Result<(), Error> {
+        if !self
+            .granted_features
+            .iter()
+            .any(|f| f == "light-estimation")
+        {
+            return Err(Error::UnsupportedFeature("light-estimation".into()));
+        }
+        self.with_per_session(|s| s.light_probe_requested = true);
+        Ok(())
+    }
+
+    fn set_layer_foveation(&mut self, layer_id: LayerId, level: f32) -> f32 {
+        // Only projection layers can be foveated
+        if !matches!(self.layer_inits.get(&layer_id), Some(init) if init.is_projection()) {
+            return 0.0;
+        }
+        let mut data = self.data.lock().unwrap();
+        match data.layer_foveations.get_mut(&layer_id) {
+            Some(foveation) => {
+                *foveation = level;
+                level
+            }
+            None => 0.0,
+        }
+    }
+
+    fn update_frame_rate(&mut self, rate: f32) -> f32 {
+        let supported = self
+            .data
+            .lock()
+            .unwrap()
+            .supported_frame_rates
+            .contains(&rate);
+        if supported && rate != self.frame_rate {
+            self.frame_rate = rate;
+            self.frame_rate_changed = true;
+        }
+        self.frame_rate
+    }
+
+    fn supported_frame_rates(&self) -> Vec<f32> {
+        self.data.lock().unwrap().supported_frame_rates.clone()
+    }
+
+    fn request_viewport_scale(&mut self, scale: f32) {
+        if let Ok(layer_manager) = self.layer_manager() {
+            layer_manager.set_viewport_scale(scale);
+        }
+    }
+
     fn reference_space_bounds(&self) -> Option<Vec<Point2D<f32, Floor>>> {
         let bounds = self.data.lock().unwrap().bounds_geometry.clone();
         Some(bounds)
@@ -317,7 +697,79 @@ impl DeviceAPI for HeadlessDevice {
 
 impl HeadlessMockDiscovery {
     pub fn new() -> HeadlessMockDiscovery {
-        HeadlessMockDiscovery {}
+        HeadlessMockDiscovery {
+            #[cfg(feature = "recording")]
+            recording: None,
+        }
+    }
+
+    /// Record every frame of the sessions of devices connected to this discovery into
+    /// a subdirectory of `dir` per session, as a PNG for each view of each layer and
+    /// a JSON file with the viewer pose and inputs
+    #[cfg(feature = "recording")]
+    pub fn with_recording(dir: PathBuf) -> HeadlessMockDiscovery {
+        HeadlessMockDiscovery {
+            recording: Some(dir),
+        }
+    }
+
+    /// Connect a device that also applies each of `updates` at the start of the first frame
+    /// at or after its time on the device's clock
+    pub(crate) fn simulate_device_playback(
+        &mut self,
+        init: MockDeviceInit,
+        receiver: Receiver<MockDeviceMsg>,
+        updates: Vec<(Duration, MockDeviceMsg)>,
+    ) -> Result<Box<dyn DiscoveryAPI<SurfmanGL>>, Error> {
+        let viewer_origin = init.viewer_origin.clone();
+        let floor_transform = init.floor_origin.map(|f| f.inverse());
+        let views = init.views.clone();
+        let mut data = HeadlessDeviceData {
+            floor_transform,
+            viewer_origin,
+            supported_features: init.supported_features,
+            views,
+            needs_floor_update: false,
+            inputs: vec![],
+            sessions: vec![],
+            disconnected: false,
+            world: Bvh::default(),
+            planes: vec![],
+            meshes: vec![],
+            next_id: 0,
+            bounds_geometry: vec![],
+            light_estimate: None,
+            camera_image: None,
+            camera_image_generation: 0,
+            layer_foveations: HashMap::new(),
+            capture_requests: vec![],
+            clock: if init.virtual_clock {
+                Clock::virtual_time()
+            } else {
+                Clock::real_time()
+            },
+            supported_frame_rates: init
+                .supported_frame_rates
+                .into_iter()
+                .filter(|rate| rate.is_finite() && *rate > 0.0)
+                .collect(),
+            scheduled: updates.into(),
+        };
+        data.set_world(init.world);
+        let data = Arc::new(Mutex::new(data));
+        let data_ = data.clone();
+
+        thread::spawn(move || {
+            run_loop(receiver, data_);
+        });
+        Ok(Box::new(HeadlessDiscovery {
+            data,
+            supports_vr: init.supports_vr,
+            supports_inline: init.supports_inline,
+            supports_ar: init.supports_ar,
+            #[cfg(feature = "recording")]
+            recording: self.recording.clone(),
+        }))
     }
 }
 
@@ -364,13 +816,30 @@ impl HeadlessDeviceData {
                 input_changed: false,
             })
             .collect();
+        let anchor_poses = s
+            .anchors
+            .iter()
+            .map(|&(id, pose)| AnchorPose {
+                id,
+                pose: Some(pose),
+            })
+            .collect();
         Frame {
             pose,
             inputs,
             events: vec![],
             sub_images,
             hit_test_results: vec![],
-            predicted_display_time: 0.0,
+            transient_hit_test_results: vec![],
+            anchor_poses,
+            detected_planes: vec![],
+            mesh_updates: vec![],
+            light_estimate: if s.light_probe_requested {
+                self.light_estimate.clone()
+            } else {
+                None
+            },
+            predicted_display_time: self.clock.now_nanos(),
         }
     }
 
@@ -386,6 +855,52 @@ impl HeadlessDeviceData {
         Viewports { viewports: vec }
     }
 
+    fn set_world(&mut self, world: Option<MockWorld>) {
+        // Planes and meshes that are unchanged by the new world keep the time they last changed
+        let now = self.clock.now().as_secs_f64() * 1000.0;
+        let previous_planes = std::mem::take(&mut self.planes);
+        self.planes = world
+            .iter()
+            .flat_map(|world| world.regions.iter().enumerate())
+            .filter(|(_, region)| matches!(region.ty, EntityType::Plane))
+            .filter_map(|(i, region)| {
+                let mut plane =
+                    DetectedPlane::from_triangles(PlaneId(i as u32), &region.faces, now)?;
+                if let Some(previous) = previous_planes
+                    .iter()
+                    .find(|previous| previous.id == plane.id && previous.same_geometry(&plane))
+                {
+                    plane.last_changed_time = previous.last_changed_time;
+                }
+                Some(plane)
+            })
+            .collect();
+        let previous_meshes = std::mem::take(&mut self.meshes);
+        self.meshes = world
+            .iter()
+            .flat_map(|world| world.regions.iter().enumerate())
+            .filter(|(_, region)| matches!(region.ty, EntityType::Mesh))
+            .map(|(i, region)| {
+                let mut mesh =
+                    DetectedMesh::from_triangles(MeshId(i as u32), &region.faces, None, now);
+                if let Some(previous) = previous_meshes
+                    .iter()
+                    .find(|previous| previous.id == mesh.id && previous.same_geometry(&mesh))
+                {
+                    mesh.last_changed_time = previous.last_changed_time;
+                }
+                mesh
+            })
+            .collect();
+        self.world = Bvh::from_groups(world.iter().flat_map(|world| {
+            world
+                .regions
+                .iter()
+                .enumerate()
+                .map(|(i, region)| (&region.faces[..], (i as u32, region.ty)))
+        }));
+    }
+
     fn trigger_select(&mut self, id: InputId, kind: SelectKind, event: SelectEvent) {
         for i in 0..self.sessions.len() {
             let frame = self.get_frame(&self.sessions[i], Vec::new());
@@ -395,10 +910,20 @@ impl HeadlessDeviceData {
         }
     }
 
+    /// Apply the scheduled updates whose time has come
+    fn apply_scheduled(&mut self) {
+        let now = self.clock.now();
+        while matches!(self.scheduled.front(), Some(&(time, _)) if time <= now) {
+            if let Some((_, msg)) = self.scheduled.pop_front() {
+                self.handle_msg(msg);
+            }
+        }
+    }
+
     fn handle_msg(&mut self, msg: MockDeviceMsg) -> bool {
         match msg {
-            MockDeviceMsg::SetWorld(w) => self.world = Some(w),
-            MockDeviceMsg::ClearWorld => self.world = None,
+            MockDeviceMsg::SetWorld(w) => self.set_world(Some(w)),
+            MockDeviceMsg::ClearWorld => self.set_world(None),
             MockDeviceMsg::SetViewerOrigin(viewer_origin) => {
                 self.viewer_origin = viewer_origin;
             }
@@ -523,6 +1048,18 @@ impl HeadlessDeviceData {
             MockDeviceMsg::SetBoundsGeometry(g) => {
                 self.bounds_geometry = g;
             }
+            MockDeviceMsg::SetLightEstimate(estimate) => {
+                self.light_estimate = estimate;
+            }
+            MockDeviceMsg::SetCameraImage(image) => {
+                self.camera_image = image;
+                self.camera_image_generation += 1;
+            }
+            MockDeviceMsg::AdvanceTime(duration) => self.clock.advance(duration),
+            MockDeviceMsg::CaptureFrame(sender) => self.capture_requests.push(sender),
+            MockDeviceMsg::GetLayerFoveation(layer_id, sender) => {
+                let _ = sender.send(self.layer_foveations.get(&layer_id).copied());
+            }
             MockDeviceMsg::SimulateResetPose => {
                 with_all_sessions!(self, |s| s.events.callback(Event::ReferenceSpaceChanged(
                     BaseSpace::Local,
@@ -533,7 +1070,7 @@ impl HeadlessDeviceData {
         true
     }
 
-    fn native_ray(&self, ray: Ray<ApiSpace>, space: Space) -> Option<Ray<Native>> {
+    fn native_origin(&self, space: Space) -> Option<RigidTransform3D<f32, ApiSpace, Native>> {
         let origin: RigidTransform3D<f32, ApiSpace, Native> = match space.base {
             BaseSpace::Local => RigidTransform3D::identity(),
             BaseSpace::Floor => self.floor_transform?.inverse().cast_unit(),
@@ -553,7 +1090,89 @@ impl HeadlessDeviceData {
                 .cast_unit(),
             BaseSpace::Joint(..) => panic!("Cannot request mocking backend with hands"),
         };
-        let space_origin = space.offset.then(&origin);
+        Some(space.offset.then(&origin))
+    }
+
+    /// Synthesize depth for each view by raycasting the mock world
+    fn add_depth(&self, views: &mut Views, viewports: &Viewports, format: DepthDataFormat) {
+        let viewports = &viewports.viewports;
+        match views {
+            Views::Mono(one) => one.depth = Some(self.depth(one, viewports[0], format)),
+            Views::Stereo(one, two) => {
+                one.depth = Some(self.depth(one, viewports[0], format));
+                two.depth = Some(self.depth(two, viewports[1], format));
+            }
+            _ => (),
+        }
+    }
+
+    fn depth<Eye>(
+        &self,
+        view: &View<Eye>,
+        viewport: Rect<i32, Viewport>,
+        format: DepthDataFormat,
+    ) -> DepthInformation {
+        let width = (viewport.size.width / DEPTH_DOWNSCALE).max(1) as u32;
+        let height = (viewport.size.height / DEPTH_DOWNSCALE).max(1) as u32;
+        let unproject = view.projection.inverse();
+        let mut data = Vec::with_capacity((width * height) as usize * format.bytes_per_pixel());
+        for row in 0..height {
+            for column in 0..width {
+                // The center of the pixel in normalized device coordinates, with the top row first
+                let x = (column as f32 + 0.5) / width as f32 * 2. - 1.;
+                let y = 1. - (row as f32 + 0.5) / height as f32 * 2.;
+                let depth = unproject
+                    .and_then(|unproject| self.depth_at(view, &unproject, x, y))
+                    .unwrap_or(0.);
+                format.encode(depth, &mut data);
+            }
+        }
+        DepthInformation {
+            width,
+            height,
+            raw_value_to_meters: format.raw_value_to_meters(),
+            norm_texture_from_norm_view: RigidTransform3D::identity(),
+            data: DepthData::Cpu(data),
+        }
+    }
+
+    /// The distance along the view's -Z axis to the nearest surface through the given point
+    fn depth_at<Eye>(
+        &self,
+        view: &View<Eye>,
+        unproject: &Transform3D<f32, Display, Eye>,
+        x: f32,
+        y: f32,
+    ) -> Option<f32> {
+        let near = unproject.transform_point3d(Point3D::new(x, y, -1.))?;
+        let direction = near.to_vector().normalize();
+        let ray = Ray {
+            origin: view.transform.translation,
+            direction: view.transform.rotation.transform_vector3d(direction),
+        };
+        let hit = self.world.nearest(ray, |_| true)?;
+        Some(hit.distance * -direction.z)
+    }
+
+    fn hit_test(&self, id: HitTestId, ray: Ray<Native>, types: EntityTypes) -> Vec<HitTestResult> {
+        self.world
+            .intersect(ray, |&(_, ty)| types.is_type(ty))
+            .into_iter()
+            .map(|hit| {
+                let (index, entity_type) = *hit.payload;
+                HitTestResult {
+                    id,
+                    space: hit.space,
+                    distance: hit.distance,
+                    entity_type,
+                    entity_id: Some(index),
+                }
+            })
+            .collect()
+    }
+
+    fn native_ray(&self, ray: Ray<ApiSpace>, space: Space) -> Option<Ray<Native>> {
+        let space_origin = self.native_origin(space)?;
 
         let origin_rigid: RigidTransform3D<f32, ApiSpace, ApiSpace> = ray.origin.into();
         Some(Ray {
@@ -0,0 +1,209 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Recording of headless sessions to a directory, so failing runs can be inspected afterwards.
+//! Each frame is written as one PNG per layer and view, along with a JSON file
+//! describing the viewer pose, the inputs and the images.
+
+use euclid::{Rect, RigidTransform3D, Size2D};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use webxr_api::{Frame, InputFrame, LayerId, LayerImage, Native, Viewer, Viewport, Viewports};
+
+pub(crate) struct Recorder {
+    dir: PathBuf,
+    next_frame: u32,
+    frame: Option<RecordedFrame>,
+}
+
+// The parts of a frame that are known when it begins
+struct RecordedFrame {
+    viewer: Option<RigidTransform3D<f32, Viewer, Native>>,
+    inputs: Vec<InputFrame>,
+    /// The viewport of each view of each layer, in the layer's color texture
+    views: HashMap<LayerId, Vec<Rect<i32, Viewport>>>,
+}
+
+impl Recorder {
+    pub(crate) fn new(dir: PathBuf) -> Recorder {
+        Recorder {
+            dir,
+            next_frame: 0,
+            frame: None,
+        }
+    }
+
+    /// Layers that are reprojected are read back laid out like the device's viewports
+    pub(crate) fn begin_frame(
+        &mut self,
+        frame: &Frame,
+        viewports: &Viewports,
+        is_reprojected: impl Fn(LayerId) -> bool,
+    ) {
+        let views = frame
+            .sub_images
+            .iter()
+            .map(|sub_images| {
+                if is_reprojected(sub_images.layer_id) {
+                    return (sub_images.layer_id, viewports.viewports.clone());
+                }
+                let rects = sub_images
+                    .view_sub_images
+                    .iter()
+                    .map(|sub_image| match sub_image.texture_array_index {
+                        // Texture arrays are read back in the device's layout
+                        Some(index) => Rect::new(
+                            viewports.viewports[index as usize].origin,
+                            sub_image.viewport.size,
+                        ),
+                        None => sub_image.viewport,
+                    })
+                    .collect();
+                (sub_images.layer_id, rects)
+            })
+            .collect();
+        self.frame = Some(RecordedFrame {
+            viewer: frame.pose.as_ref().map(|pose| pose.transform),
+            inputs: frame.inputs.clone(),
+            views,
+        });
+    }
+
+    pub(crate) fn end_frame(&mut self, images: &[(LayerId, LayerImage)]) -> io::Result<()> {
+        let frame = match self.frame.take() {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        let index = self.next_frame;
+        self.next_frame += 1;
+        fs::create_dir_all(&self.dir)?;
+
+        let mut layers = vec![];
+        for (layer_index, (layer_id, image)) in images.iter().enumerate() {
+            let rects = match frame.views.get(layer_id) {
+                Some(rects) => rects,
+                None => continue,
+            };
+            let mut views = vec![];
+            for (view_index, rect) in rects.iter().enumerate() {
+                let name = format!(
+                    "frame-{:05}-layer-{}-view-{}.png",
+                    index, layer_index, view_index
+                );
+                write_png(self.dir.join(&name), image, *rect)?;
+                views.push(ViewRecord {
+                    x: rect.origin.x,
+                    y: rect.origin.y,
+                    width: rect.size.width,
+                    height: rect.size.height,
+                    image: name,
+                });
+            }
+            layers.push(LayerRecord { views });
+        }
+
+        let inputs = frame
+            .inputs
+            .iter()
+            .map(|input| InputRecord {
+                id: input.id.0,
+                target_ray: input.target_ray_origin.as_ref().map(TransformRecord::new),
+                grip: input.grip_origin.as_ref().map(TransformRecord::new),
+                pressed: input.pressed,
+                squeezed: input.squeezed,
+            })
+            .collect();
+
+        let record = FrameRecord {
+            frame: index,
+            viewer: frame.viewer.as_ref().map(TransformRecord::new),
+            inputs,
+            layers,
+        };
+        let file = File::create(self.dir.join(format!("frame-{:05}.json", index)))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &record)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+// The JSON file written for each frame. Non-finite numbers are written as null.
+#[derive(Serialize)]
+struct FrameRecord {
+    frame: u32,
+    viewer: Option<TransformRecord>,
+    inputs: Vec<InputRecord>,
+    layers: Vec<LayerRecord>,
+}
+
+#[derive(Serialize)]
+struct InputRecord {
+    id: u32,
+    target_ray: Option<TransformRecord>,
+    grip: Option<TransformRecord>,
+    pressed: bool,
+    squeezed: bool,
+}
+
+#[derive(Serialize)]
+struct LayerRecord {
+    views: Vec<ViewRecord>,
+}
+
+#[derive(Serialize)]
+struct ViewRecord {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    image: String,
+}
+
+#[derive(Serialize)]
+struct TransformRecord {
+    position: [f32; 3],
+    orientation: [f32; 4],
+}
+
+impl TransformRecord {
+    fn new<Src, Dst>(transform: &RigidTransform3D<f32, Src, Dst>) -> TransformRecord {
+        let (position, orientation) = (transform.translation, transform.rotation);
+        TransformRecord {
+            position: [position.x, position.y, position.z],
+            orientation: [orientation.i, orientation.j, orientation.k, orientation.r],
+        }
+    }
+}
+
+/// Write the part of an image in a view's viewport to a PNG file, flipping it
+/// since the image starts at the bottom row
+fn write_png(path: PathBuf, image: &LayerImage, rect: Rect<i32, Viewport>) -> io::Result<()> {
+    let size = Size2D::new(image.width as i32, image.height as i32);
+    let rect = rect.intersection(&Rect::from_size(size));
+    let rect = match rect {
+        Some(rect) => rect.to_usize(),
+        None => return Ok(()),
+    };
+    let stride = image.width as usize * 4;
+    let mut data = Vec::with_capacity(rect.area() * 4);
+    for row in rect.y_range().rev() {
+        let start = row * stride + rect.origin.x * 4;
+        data.extend_from_slice(&image.data[start..start + rect.size.width * 4]);
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, rect.size.width as u32, rect.size.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    writer
+        .write_image_data(&data)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+}
@@ -0,0 +1,256 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Reprojection of cylinder and equirect layers into each view, the way glwindow's
+//! shaders composite them, so that captured and recorded frames show these layers
+//! as they would be seen. Like depth sensing, this casts a ray per pixel on the CPU.
+
+use euclid::{Point3D, Rect, RigidTransform3D, Transform3D, Vector3D};
+use webxr_api::{
+    ApiSpace, Display, LayerImage, LayerInit, Native, View, Viewport, Viewports, Views,
+};
+
+/// The coordinate space of any view
+enum Eye {}
+
+enum Surface {
+    Cylinder {
+        radius: f32,
+        central_angle: f32,
+        height: f32,
+    },
+    Equirect {
+        radius: f32,
+        central_horizontal_angle: f32,
+        upper_vertical_angle: f32,
+        lower_vertical_angle: f32,
+    },
+}
+
+struct ReprojectionView {
+    transform: RigidTransform3D<f32, Eye, Native>,
+    unproject: Transform3D<f32, Display, Eye>,
+    viewport: Rect<i32, Viewport>,
+    /// The region of the layer's texture shown to this view
+    sub_image: Rect<i32, Viewport>,
+}
+
+/// How to draw a layer into each view for a frame
+pub(crate) struct Reprojection {
+    surface: Surface,
+    layer_from_native: RigidTransform3D<f32, Native, ApiSpace>,
+    views: Vec<ReprojectionView>,
+}
+
+impl Reprojection {
+    /// The reprojection of a layer at the given pose, or None for layers that are
+    /// read back as they were submitted
+    pub(crate) fn new(
+        init: &LayerInit,
+        pose: RigidTransform3D<f32, ApiSpace, Native>,
+        views: &Views,
+        viewports: &Viewports,
+    ) -> Option<Reprojection> {
+        let surface = match *init {
+            LayerInit::CylinderLayer {
+                radius,
+                central_angle,
+                aspect_ratio,
+                ..
+            } => Surface::Cylinder {
+                radius,
+                central_angle,
+                height: radius * central_angle / aspect_ratio,
+            },
+            LayerInit::EquirectLayer {
+                radius,
+                central_horizontal_angle,
+                upper_vertical_angle,
+                lower_vertical_angle,
+                ..
+            } => Surface::Equirect {
+                radius,
+                central_horizontal_angle,
+                upper_vertical_angle,
+                lower_vertical_angle,
+            },
+            _ => return None,
+        };
+        let sub_images = init.view_sub_image_rects(viewports);
+        let views = match *views {
+            Views::Mono(ref one) => vec![reprojection_view(one)],
+            Views::Stereo(ref one, ref two) => {
+                vec![reprojection_view(one), reprojection_view(two)]
+            }
+            _ => return None,
+        };
+        let views = views
+            .into_iter()
+            .zip(&viewports.viewports)
+            .zip(&sub_images)
+            .filter_map(|((view, &viewport), &sub_image)| {
+                let (transform, unproject) = view?;
+                Some(ReprojectionView {
+                    transform,
+                    unproject,
+                    viewport,
+                    sub_image,
+                })
+            })
+            .collect();
+        Some(Reprojection {
+            surface,
+            layer_from_native: pose.inverse(),
+            views,
+        })
+    }
+
+    /// Draw the layer's submitted image into each view's viewport
+    pub(crate) fn reproject(&self, image: &LayerImage) -> LayerImage {
+        // The image is laid out like the device's viewports
+        let width = self.views.iter().map(|view| view.viewport.max_x()).max();
+        let height = self.views.iter().map(|view| view.viewport.max_y()).max();
+        let width = width.unwrap_or(0).max(0) as u32;
+        let height = height.unwrap_or(0).max(0) as u32;
+        let mut data = vec![0; width as usize * height as usize * 4];
+        for view in &self.views {
+            let viewport = view.viewport.to_f32();
+            for y in view.viewport.y_range() {
+                for x in view.viewport.x_range() {
+                    // The center of the pixel in normalized device coordinates
+                    let ndc_x =
+                        (x as f32 + 0.5 - viewport.origin.x) / viewport.size.width * 2. - 1.;
+                    let ndc_y =
+                        (y as f32 + 0.5 - viewport.origin.y) / viewport.size.height * 2. - 1.;
+                    let color = match self.texture_coord(view, ndc_x, ndc_y) {
+                        Some((u, v)) => sample(image, view.sub_image, u, v),
+                        None => continue,
+                    };
+                    let start = (y as usize * width as usize + x as usize) * 4;
+                    data[start..start + 4].copy_from_slice(&color);
+                }
+            }
+        }
+        LayerImage {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Where the ray through a point of a view hits the layer, in texture coordinates
+    fn texture_coord(&self, view: &ReprojectionView, x: f32, y: f32) -> Option<(f32, f32)> {
+        let near = view.unproject.transform_point3d(Point3D::new(x, y, -1.))?;
+        let direction = view
+            .transform
+            .rotation
+            .transform_vector3d(near.to_vector().normalize());
+        let origin = self
+            .layer_from_native
+            .rotation
+            .transform_vector3d(view.transform.translation)
+            + self.layer_from_native.translation;
+        let direction = self
+            .layer_from_native
+            .rotation
+            .transform_vector3d(direction);
+        let (u, v) = self.surface.texture_coord(origin, direction)?;
+        if u < 0. || u > 1. || v < 0. || v > 1. {
+            return None;
+        }
+        Some((u, v))
+    }
+}
+
+impl Surface {
+    /// The same intersections as glwindow's `CYLINDER_FRAGMENT_SHADER` and `EQUIRECT_FRAGMENT_SHADER`
+    fn texture_coord(
+        &self,
+        origin: Vector3D<f32, ApiSpace>,
+        direction: Vector3D<f32, ApiSpace>,
+    ) -> Option<(f32, f32)> {
+        match *self {
+            Surface::Cylinder {
+                radius,
+                central_angle,
+                height,
+            } => {
+                // Intersect the ray with the inside of the cylinder x^2 + z^2 = radius^2
+                let a = direction.x * direction.x + direction.z * direction.z;
+                let b = 2. * (origin.x * direction.x + origin.z * direction.z);
+                let c = origin.x * origin.x + origin.z * origin.z - radius * radius;
+                let discriminant = b * b - 4. * a * c;
+                if a == 0. || discriminant < 0. {
+                    return None;
+                }
+                let t = (-b + discriminant.sqrt()) / (2. * a);
+                if t < 0. {
+                    return None;
+                }
+                let point = origin + direction * t;
+                Some((
+                    point.x.atan2(-point.z) / central_angle + 0.5,
+                    point.y / height + 0.5,
+                ))
+            }
+            Surface::Equirect {
+                radius,
+                central_horizontal_angle,
+                upper_vertical_angle,
+                lower_vertical_angle,
+            } => {
+                let mut point = direction;
+                if radius > 0. {
+                    // Intersect the ray with the inside of the sphere
+                    let b = origin.dot(direction);
+                    let c = origin.dot(origin) - radius * radius;
+                    let discriminant = b * b - c;
+                    if discriminant < 0. {
+                        return None;
+                    }
+                    let t = -b + discriminant.sqrt();
+                    if t < 0. {
+                        return None;
+                    }
+                    point = (origin + direction * t).normalize();
+                }
+                let longitude = point.x.atan2(-point.z);
+                let latitude = point.y.max(-1.).min(1.).asin();
+                Some((
+                    longitude / central_horizontal_angle + 0.5,
+                    (latitude - lower_vertical_angle)
+                        / (upper_vertical_angle - lower_vertical_angle),
+                ))
+            }
+        }
+    }
+}
+
+fn reprojection_view<E>(
+    view: &View<E>,
+) -> Option<(
+    RigidTransform3D<f32, Eye, Native>,
+    Transform3D<f32, Display, Eye>,
+)> {
+    let unproject = view.projection.inverse()?;
+    Some((
+        view.transform.cast_unit(),
+        Transform3D::from_untyped(&unproject.to_untyped()),
+    ))
+}
+
+/// The nearest pixel of a sub image to the given texture coordinates
+fn sample(image: &LayerImage, sub_image: Rect<i32, Viewport>, u: f32, v: f32) -> [u8; 4] {
+    let x = sub_image.origin.x + (u * sub_image.size.width as f32) as i32;
+    let y = sub_image.origin.y + (v * sub_image.size.height as f32) as i32;
+    let x = x.min(sub_image.max_x() - 1).min(image.width as i32 - 1);
+    let y = y.min(sub_image.max_y() - 1).min(image.height as i32 - 1);
+    if x < 0 || y < 0 {
+        return [0; 4];
+    }
+    let start = (y as usize * image.width as usize + x as usize) * 4;
+    let mut color = [0; 4];
+    color.copy_from_slice(&image.data[start..start + 4]);
+    color
+}
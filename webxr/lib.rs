@@ -13,6 +13,9 @@ pub mod headless;
 #[cfg(feature = "openxr-api")]
 pub mod openxr;
 
+#[cfg(feature = "replay")]
+pub mod replay;
+
 pub mod surfman_layer_manager;
 pub use surfman_layer_manager::SurfmanGL;
 pub use surfman_layer_manager::SurfmanLayerManager;
@@ -1,4 +1,4 @@
-use crate::gl_utils::GlClearer;
+use crate::gl_utils::{create_texture, supported_depth_stencil_format, GlClearer, TextureFormat};
 use crate::SurfmanGL;
 
 use euclid::Box2D;
@@ -9,7 +9,6 @@ use euclid::Rotation3D;
 use euclid::Size2D;
 use euclid::Transform3D;
 use euclid::Vector3D;
-use glow::PixelUnpackData;
 use glow::{self as gl, HasContext};
 use interaction_profiles::{get_profiles_from_path, get_supported_interaction_profiles};
 use log::{error, warn};
@@ -34,10 +33,12 @@ use surfman::Device as SurfmanDevice;
 use surfman::Error as SurfmanError;
 use surfman::SurfaceTexture;
 use webxr_api;
+use webxr_api::scale_viewport;
 use webxr_api::util::{self, ClipPlanes};
 use webxr_api::BaseSpace;
 use webxr_api::Capture;
 use webxr_api::ContextId;
+use webxr_api::DepthStencilFormat;
 use webxr_api::DeviceAPI;
 use webxr_api::DiscoveryAPI;
 use webxr_api::Display;
@@ -52,6 +53,7 @@ use webxr_api::InputSource;
 use webxr_api::LayerGrandManager;
 use webxr_api::LayerId;
 use webxr_api::LayerInit;
+use webxr_api::LayerLayout;
 use webxr_api::LayerManager;
 use webxr_api::LayerManagerAPI;
 use webxr_api::LeftEye;
@@ -66,6 +68,7 @@ use webxr_api::SessionInit;
 use webxr_api::SessionMode;
 use webxr_api::SubImage;
 use webxr_api::SubImages;
+use webxr_api::TextureType;
 use webxr_api::View;
 use webxr_api::ViewerPose;
 use webxr_api::Viewport;
@@ -194,6 +197,8 @@ impl<Eye> ViewInfo<Eye> {
         View {
             transform: transform(&self.view.pose),
             projection: self.cached_projection,
+            depth: None,
+            camera: None,
         }
     }
 }
@@ -422,11 +427,18 @@ struct OpenXrLayerManager {
     clearer: GlClearer,
     _passthrough: Option<Passthrough>,
     passthrough_layer: Option<PassthroughLayer>,
+    /// Used to position quad layers in viewer space
+    view_space: Option<Space>,
+    /// The viewport scale of projection layers, which is applied from the next frame
+    viewport_scale: f32,
+    /// The viewport scale of projection layers in the current frame
+    frame_viewport_scale: f32,
 }
 
 struct OpenXrLayer {
+    init: LayerInit,
     swapchain: Swapchain<Backend>,
-    depth_stencil_texture: Option<gl::NativeTexture>,
+    depth_stencil_texture: Option<(gl::NativeTexture, DepthStencilFormat)>,
     size: Size2D<i32, Viewport>,
     images: Vec<<Backend as Graphics>::SwapchainImage>,
     surface_textures: Vec<Option<SurfaceTexture>>,
@@ -445,6 +457,10 @@ impl OpenXrLayerManager {
         let layers = Vec::new();
         let openxr_layers = HashMap::new();
         let clearer = GlClearer::new(should_reverse_winding);
+        let view_space = session
+            .create_reference_space(ReferenceSpaceType::VIEW, IDENTITY_POSE)
+            .map_err(|e| warn!("Session::create_reference_space {:?}", e))
+            .ok();
         OpenXrLayerManager {
             session,
             shared_data,
@@ -454,14 +470,18 @@ impl OpenXrLayerManager {
             clearer,
             _passthrough,
             passthrough_layer,
+            view_space,
+            viewport_scale: 1.0,
+            frame_viewport_scale: 1.0,
         }
     }
 }
 
 impl OpenXrLayer {
     fn new(
+        init: LayerInit,
         swapchain: Swapchain<Backend>,
-        depth_stencil_texture: Option<gl::NativeTexture>,
+        depth_stencil_texture: Option<(gl::NativeTexture, DepthStencilFormat)>,
         size: Size2D<i32, Viewport>,
     ) -> Result<OpenXrLayer, Error> {
         let images = swapchain
@@ -471,6 +491,7 @@ impl OpenXrLayer {
         let mut surface_textures = Vec::new();
         surface_textures.resize_with(images.len(), || None);
         Ok(OpenXrLayer {
+            init,
             swapchain,
             depth_stencil_texture,
             size,
@@ -512,6 +533,27 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         context_id: ContextId,
         init: LayerInit,
     ) -> Result<LayerId, Error> {
+        match init {
+            LayerInit::Overlay { .. } => {
+                return Err(Error::UnsupportedFeature("dom-overlay".into()));
+            }
+            // TODO: use XR_KHR_composition_layer_cylinder, XR_KHR_composition_layer_equirect2
+            // and XR_KHR_composition_layer_cube
+            LayerInit::CylinderLayer { .. }
+            | LayerInit::EquirectLayer { .. }
+            | LayerInit::CubeLayer { .. } => {
+                return Err(Error::UnsupportedFeature("layers".into()));
+            }
+            // TODO: use an array swapchain
+            LayerInit::ProjectionLayer {
+                texture_type: TextureType::TextureArray,
+                ..
+            } => {
+                return Err(Error::UnsupportedFeature("layers".into()));
+            }
+            _ => (),
+        }
+
         let guard = self.shared_data.lock().unwrap();
         let data = guard.as_ref().unwrap();
 
@@ -538,38 +580,30 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             .create_swapchain(&swapchain_create_info)
             .map_err(|e| Error::BackendSpecific(format!("Session::create_swapchain {:?}", e)))?;
 
-        // TODO: Treat depth and stencil separately?
         // TODO: Use the openxr API for depth/stencil swap chains?
-        let has_depth_stencil = match init {
-            LayerInit::WebGLLayer { stencil, depth, .. } => stencil | depth,
-            LayerInit::ProjectionLayer { stencil, depth, .. } => stencil | depth,
-        };
-        let depth_stencil_texture = if has_depth_stencil {
-            let gl = contexts
-                .bindings(device, context_id)
-                .ok_or(Error::NoMatchingDevice)?;
-            unsafe {
-                let depth_stencil_texture = gl.create_texture().ok();
-                gl.bind_texture(gl::TEXTURE_2D, depth_stencil_texture);
-                gl.tex_image_2d(
-                    gl::TEXTURE_2D,
-                    0,
-                    gl::DEPTH24_STENCIL8 as _,
-                    texture_size.width,
-                    texture_size.height,
-                    0,
-                    gl::DEPTH_STENCIL,
-                    gl::UNSIGNED_INT_24_8,
-                    PixelUnpackData::Slice(None),
-                );
-                depth_stencil_texture
+        let depth_stencil_texture = match init.depth_stencil_format() {
+            Some(format) => {
+                let gl = contexts
+                    .bindings(device, context_id)
+                    .ok_or(Error::NoMatchingDevice)?;
+                let format = supported_depth_stencil_format(gl, format);
+                let texture_format = TextureFormat::depth_stencil(format);
+                let texture = unsafe {
+                    create_texture(
+                        gl,
+                        texture_format,
+                        texture_size.width,
+                        texture_size.height,
+                        None,
+                    )?
+                };
+                Some((texture, format))
             }
-        } else {
-            None
+            None => None,
         };
 
         let layer_id = LayerId::new();
-        let openxr_layer = OpenXrLayer::new(swapchain, depth_stencil_texture, texture_size)?;
+        let openxr_layer = OpenXrLayer::new(init, swapchain, depth_stencil_texture, texture_size)?;
         self.layers.push((context_id, layer_id));
         self.openxr_layers.insert(layer_id, openxr_layer);
         Ok(layer_id)
@@ -586,7 +620,7 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             .destroy_layer(device, contexts, context_id, layer_id);
         self.layers.retain(|&ids| ids != (context_id, layer_id));
         if let Some(mut layer) = self.openxr_layers.remove(&layer_id) {
-            if let Some(depth_stencil_texture) = layer.depth_stencil_texture {
+            if let Some((depth_stencil_texture, _)) = layer.depth_stencil_texture {
                 let gl = contexts.bindings(device, context_id).unwrap();
                 unsafe { gl.delete_texture(depth_stencil_texture) };
             }
@@ -641,10 +675,24 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         }
 
         let viewports = data.viewports();
-        let primary_views = layers
+        let viewport_scale = self.frame_viewport_scale;
+        let projection_layer_ids = layers
+            .iter()
+            .map(|&(_, layer_id)| layer_id)
+            .filter(|layer_id| {
+                openxr_layers.get(layer_id).map_or(false, |layer| {
+                    matches!(
+                        layer.init,
+                        LayerInit::WebGLLayer { .. } | LayerInit::ProjectionLayer { .. }
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let primary_views = projection_layer_ids
             .iter()
-            .filter_map(|&(_, layer_id)| {
-                let openxr_layer = openxr_layers.get(&layer_id)?;
+            .filter_map(|layer_id| {
+                let openxr_layer = openxr_layers.get(layer_id)?;
+                let rects = openxr_layer.init.view_sub_image_rects(&viewports);
                 Some([
                     openxr::CompositionLayerProjectionView::new()
                         .pose(data.left.view.pose)
@@ -653,7 +701,7 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                             openxr::SwapchainSubImage::new()
                                 .swapchain(&openxr_layer.swapchain)
                                 .image_array_index(0)
-                                .image_rect(image_rect(viewports.viewports[0])),
+                                .image_rect(image_rect(scale_viewport(rects[0], viewport_scale))),
                         ),
                     openxr::CompositionLayerProjectionView::new()
                         .pose(data.right.view.pose)
@@ -662,7 +710,7 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                             openxr::SwapchainSubImage::new()
                                 .swapchain(&openxr_layer.swapchain)
                                 .image_array_index(0)
-                                .image_rect(image_rect(viewports.viewports[1])),
+                                .image_rect(image_rect(scale_viewport(rects[1], viewport_scale))),
                         ),
                 ])
             })
@@ -678,9 +726,18 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             })
             .collect::<Vec<_>>();
 
+        // Quad layers are composited after all projection layers
+        let view_space = self.view_space.as_ref();
+        let quad_layers = layers
+            .iter()
+            .filter_map(|&(_, layer_id)| openxr_layers.get(&layer_id))
+            .flat_map(|openxr_layer| quad_layers(openxr_layer, &data.space, view_space, &viewports))
+            .collect::<Vec<_>>();
+
         let mut primary_layers = primary_layers
             .iter()
             .map(|layer| layer.deref())
+            .chain(quad_layers.iter().map(|layer| layer.deref()))
             .collect::<Vec<_>>();
 
         if let Some(passthrough_layer) = &self.passthrough_layer {
@@ -700,10 +757,11 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         if let (Some(secondary), true) = (data.secondary.as_ref(), data.secondary_active) {
             let mut s_fov = secondary.view.fov;
             std::mem::swap(&mut s_fov.angle_up, &mut s_fov.angle_down);
-            let secondary_views = layers
+            let secondary_views = projection_layer_ids
                 .iter()
-                .filter_map(|&(_, layer_id)| {
-                    let openxr_layer = openxr_layers.get(&layer_id)?;
+                .filter_map(|layer_id| {
+                    let openxr_layer = openxr_layers.get(layer_id)?;
+                    let rects = openxr_layer.init.view_sub_image_rects(&viewports);
                     Some([openxr::CompositionLayerProjectionView::new()
                         .pose(secondary.view.pose)
                         .fov(s_fov)
@@ -711,7 +769,7 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                             openxr::SwapchainSubImage::new()
                                 .swapchain(&openxr_layer.swapchain)
                                 .image_array_index(0)
-                                .image_rect(image_rect(viewports.viewports[2])),
+                                .image_rect(image_rect(scale_viewport(rects[2], viewport_scale))),
                         )])
                 })
                 .collect::<Vec<_>>();
@@ -769,6 +827,8 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
     ) -> Result<Vec<SubImages>, Error> {
         let data_guard = self.shared_data.lock().unwrap();
         let data = data_guard.as_ref().unwrap();
+        self.frame_viewport_scale = self.viewport_scale;
+        let frame_viewport_scale = self.frame_viewport_scale;
         let openxr_layers = &mut self.openxr_layers;
         let clearer = &mut self.clearer;
         self.frame_stream
@@ -804,25 +864,35 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                 let color_target = device.surface_gl_texture_target();
                 let depth_stencil_texture = openxr_layer
                     .depth_stencil_texture
-                    .map(|texture| texture.0.get());
+                    .map(|(texture, _)| texture.0.get());
+                let depth_stencil_format =
+                    openxr_layer.depth_stencil_texture.map(|(_, format)| format);
                 let texture_array_index = None;
+                let viewport_scale = match openxr_layer.init.is_projection() {
+                    true => frame_viewport_scale,
+                    false => 1.0,
+                };
                 let origin = Point2D::new(0, 0);
                 let texture_size = openxr_layer.size;
                 let sub_image = Some(SubImage {
                     color_texture,
                     depth_stencil_texture,
+                    depth_stencil_format,
                     texture_array_index,
                     viewport: Rect::new(origin, texture_size),
+                    multisample_framebuffer: None,
                 });
-                let view_sub_images = data
-                    .viewports()
-                    .viewports
-                    .iter()
-                    .map(|&viewport| SubImage {
+                let view_sub_images = openxr_layer
+                    .init
+                    .view_sub_image_rects(&data.viewports())
+                    .into_iter()
+                    .map(|viewport| SubImage {
                         color_texture,
                         depth_stencil_texture,
+                        depth_stencil_format,
                         texture_array_index,
-                        viewport,
+                        viewport: scale_viewport(viewport, viewport_scale),
+                        multisample_framebuffer: None,
                     })
                     .collect();
                 clearer.clear(
@@ -833,6 +903,7 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                     NonZeroU32::new(color_texture).map(glow::NativeTexture),
                     color_target,
                     openxr_layer.depth_stencil_texture,
+                    None,
                 );
                 Ok(SubImages {
                     layer_id,
@@ -842,6 +913,69 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             })
             .collect()
     }
+
+    fn set_viewport_scale(&mut self, scale: f32) {
+        self.viewport_scale = scale;
+    }
+}
+
+/// The OpenXR composition layers for a quad layer, which is empty for other kinds of layer
+/// or if the quad's space can't be located by the compositor
+fn quad_layers<'a>(
+    openxr_layer: &'a OpenXrLayer,
+    local_space: &'a Space,
+    view_space: Option<&'a Space>,
+    viewports: &Viewports,
+) -> Vec<openxr::CompositionLayerQuad<'a, Backend>> {
+    let (space, transform, width, height, layout) = match openxr_layer.init {
+        LayerInit::QuadLayer {
+            space,
+            transform,
+            width,
+            height,
+            layout,
+            ..
+        } => (space, transform, width, height, layout),
+        _ => return vec![],
+    };
+    let openxr_space = match space.base {
+        BaseSpace::Local => local_space,
+        BaseSpace::Viewer => match view_space {
+            Some(view_space) => view_space,
+            None => return vec![],
+        },
+        base => {
+            warn!("Quad layers in {:?} space are not supported", base);
+            return vec![];
+        }
+    };
+    let pose = pose(&transform.then(&space.offset));
+    let size = openxr::Extent2Df { width, height };
+    let rects = openxr_layer.init.view_sub_image_rects(viewports);
+    let eyes = if matches!(layout, LayerLayout::Default | LayerLayout::Mono) {
+        vec![(openxr::EyeVisibility::BOTH, rects[0])]
+    } else {
+        vec![
+            (openxr::EyeVisibility::LEFT, rects[0]),
+            (openxr::EyeVisibility::RIGHT, rects[1]),
+        ]
+    };
+    eyes.into_iter()
+        .map(|(eye_visibility, rect)| {
+            openxr::CompositionLayerQuad::new()
+                .space(openxr_space)
+                .layer_flags(CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA)
+                .eye_visibility(eye_visibility)
+                .sub_image(
+                    openxr::SwapchainSubImage::new()
+                        .swapchain(&openxr_layer.swapchain)
+                        .image_array_index(0)
+                        .image_rect(image_rect(rect)),
+                )
+                .pose(pose)
+                .size(size)
+        })
+        .collect()
 }
 
 fn image_rect(viewport: Rect<i32, Viewport>) -> openxr::Rect2Di {
@@ -1398,6 +1532,11 @@ impl DeviceAPI for OpenXrDevice {
             events: vec![],
             sub_images,
             hit_test_results: vec![],
+            transient_hit_test_results: vec![],
+            anchor_poses: vec![],
+            detected_planes: vec![],
+            mesh_updates: vec![],
+            light_estimate: None,
             predicted_display_time: frame_state.predicted_display_time.as_nanos() as f64,
         };
 
@@ -1540,6 +1679,10 @@ impl DeviceAPI for OpenXrDevice {
         }
     }
 
+    fn request_viewport_scale(&mut self, scale: f32) {
+        self.layer_manager.set_viewport_scale(scale);
+    }
+
     fn supported_frame_rates(&self) -> Vec<f32> {
         if self.supports_updating_framerate {
             self.session
@@ -1582,6 +1725,22 @@ fn transform<Src, Dst>(pose: &Posef) -> RigidTransform3D<f32, Src, Dst> {
     RigidTransform3D::new(rotation, translation)
 }
 
+fn pose<Src, Dst>(transform: &RigidTransform3D<f32, Src, Dst>) -> Posef {
+    Posef {
+        orientation: Quaternionf {
+            x: transform.rotation.i,
+            y: transform.rotation.j,
+            z: transform.rotation.k,
+            w: transform.rotation.r,
+        },
+        position: Vector3f {
+            x: transform.translation.x,
+            y: transform.translation.y,
+            z: transform.translation.z,
+        },
+    }
+}
+
 #[inline]
 fn fov_to_projection_matrix<T, U>(fov: &Fovf, clip_planes: ClipPlanes) -> Transform3D<f32, T, U> {
     util::fov_to_projection_matrix(
@@ -0,0 +1,715 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A device that plays back a trace of viewer poses and input events, for example
+//! to reproduce a bug report from a headset deterministically.
+//!
+//! The trace is played back through the headless mock device, with a virtual clock that
+//! advances by one frame per frame. Each update is applied at the start of the first frame
+//! at or after its time, so every run sees the same updates in the same frames.
+//!
+//! A trace is a text file with one timestamped update per line, where times are in
+//! milliseconds since the start of the session, poses are a position followed by an
+//! orientation quaternion, input sources are added with their profiles, and lines
+//! starting with `#` are comments:
+//!
+//! ```text
+//! 0 viewer 0 1.6 0 0 0 0 1
+//! 0 add-input 0 right tracked-pointer oculus-touch generic-trigger-squeeze
+//! 16.6 pointer 0 0.2 1.2 -0.3 0 0 0 1
+//! 16.6 grip 0 none
+//! 33.3 button 0 touchpad 1 1 1 0 0
+//! 50 select 0 select start
+//! 66.6 remove-input 0
+//! ```
+
+use crate::headless::HeadlessMockDiscovery;
+use crate::SurfmanGL;
+use euclid::{RigidTransform3D, Rotation3D, Vector3D};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use webxr_api::{
+    DiscoveryAPI, Error, Frame, Handedness, Input, InputId, InputSource, MockButton,
+    MockButtonType, MockDeviceInit, MockDeviceMsg, MockInputInit, MockInputMsg, Native,
+    SelectEvent, SelectKind, Sender, Session, SessionBuilder, SessionInit, SessionMode,
+    TargetRayMode, Viewer,
+};
+
+/// A recorded sequence of viewer and input updates
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceEvent {
+    /// The time since the start of the session
+    pub time: Duration,
+    pub update: TraceUpdate,
+}
+
+#[derive(Clone, Debug)]
+pub enum TraceUpdate {
+    ViewerPose(Option<RigidTransform3D<f32, Viewer, Native>>),
+    /// An input source with its profiles
+    AddInput(InputId, Handedness, TargetRayMode, Vec<String>),
+    RemoveInput(InputId),
+    PointerPose(InputId, Option<RigidTransform3D<f32, Input, Native>>),
+    GripPose(InputId, Option<RigidTransform3D<f32, Input, Native>>),
+    Button(InputId, MockButton),
+    Select(InputId, SelectKind, SelectEvent),
+}
+
+/// A discovery whose device plays back a trace from the start of its first session
+pub struct ReplayDiscovery {
+    discovery: Box<dyn DiscoveryAPI<SurfmanGL>>,
+    sender: Sender<MockDeviceMsg>,
+}
+
+impl ReplayDiscovery {
+    /// The views, supported features and frame rates of the device are given by `init`,
+    /// which always has a virtual clock
+    pub fn new(init: MockDeviceInit, trace: Trace) -> Result<ReplayDiscovery, Error> {
+        let init = MockDeviceInit {
+            virtual_clock: true,
+            ..init
+        };
+        let (sender, receiver) = webxr_api::channel().or(Err(Error::CommunicationError))?;
+        let updates = trace.into_updates();
+        let discovery =
+            HeadlessMockDiscovery::new().simulate_device_playback(init, receiver, updates)?;
+        Ok(ReplayDiscovery { discovery, sender })
+    }
+
+    /// A sender of messages to the device, which are handled as they arrive
+    /// alongside the updates of the trace, for example to drive the device from tests
+    pub fn sender(&self) -> Sender<MockDeviceMsg> {
+        self.sender.clone()
+    }
+}
+
+impl DiscoveryAPI<SurfmanGL> for ReplayDiscovery {
+    fn request_session(
+        &mut self,
+        mode: SessionMode,
+        init: &SessionInit,
+        xr: SessionBuilder<SurfmanGL>,
+    ) -> Result<Session, Error> {
+        self.discovery.request_session(mode, init, xr)
+    }
+
+    fn supports_session(&self, mode: SessionMode) -> bool {
+        self.discovery.supports_session(mode)
+    }
+}
+
+impl TraceUpdate {
+    fn into_msg(self, supports_grip: bool) -> MockDeviceMsg {
+        match self {
+            TraceUpdate::ViewerPose(pose) => MockDeviceMsg::SetViewerOrigin(pose),
+            TraceUpdate::AddInput(id, handedness, target_ray_mode, profiles) => {
+                MockDeviceMsg::AddInputSource(MockInputInit {
+                    source: InputSource {
+                        handedness,
+                        target_ray_mode,
+                        id,
+                        supports_grip,
+                        hand_support: None,
+                        profiles,
+                    },
+                    pointer_origin: None,
+                    grip_origin: None,
+                    supported_buttons: vec![],
+                })
+            }
+            TraceUpdate::RemoveInput(id) => {
+                MockDeviceMsg::MessageInputSource(id, MockInputMsg::Disconnect)
+            }
+            TraceUpdate::PointerPose(id, pose) => {
+                MockDeviceMsg::MessageInputSource(id, MockInputMsg::SetPointerOrigin(pose))
+            }
+            TraceUpdate::GripPose(id, pose) => {
+                MockDeviceMsg::MessageInputSource(id, MockInputMsg::SetGripOrigin(pose))
+            }
+            TraceUpdate::Button(id, button) => {
+                MockDeviceMsg::MessageInputSource(id, MockInputMsg::UpdateButtonState(button))
+            }
+            TraceUpdate::Select(id, kind, event) => {
+                MockDeviceMsg::MessageInputSource(id, MockInputMsg::TriggerSelect(kind, event))
+            }
+        }
+    }
+}
+
+impl Trace {
+    pub fn load(path: &Path) -> Result<Trace, Error> {
+        let text = fs::read_to_string(path).map_err(|err| {
+            Error::BackendSpecific(format!("Failed to read {}: {}", path.display(), err))
+        })?;
+        Trace::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Trace, Error> {
+        let mut events = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let event = parse_event(line).ok_or_else(|| {
+                Error::BackendSpecific(format!("Invalid trace line {}: {}", index + 1, line))
+            })?;
+            events.push(event);
+        }
+        events.sort_by_key(|event| event.time);
+        Ok(Trace { events })
+    }
+
+    /// The messages that play back the trace, at their times since the start of the session
+    fn into_updates(self) -> Vec<(Duration, MockDeviceMsg)> {
+        let supports_grip: Vec<_> = self
+            .events
+            .iter()
+            .enumerate()
+            .map(|(index, event)| match event.update {
+                TraceUpdate::AddInput(id, ..) => self.records_grip(index, id),
+                _ => false,
+            })
+            .collect();
+        self.events
+            .into_iter()
+            .zip(supports_grip)
+            .map(|(event, supports_grip)| (event.time, event.update.into_msg(supports_grip)))
+            .collect()
+    }
+
+    /// Whether an input source added by the event at `index` has a grip pose before it is removed
+    fn records_grip(&self, index: usize, id: InputId) -> bool {
+        self.events[index + 1..]
+            .iter()
+            .take_while(|event| match event.update {
+                TraceUpdate::RemoveInput(other) => other != id,
+                _ => true,
+            })
+            .any(|event| match event.update {
+                TraceUpdate::GripPose(other, Some(_)) => other == id,
+                _ => false,
+            })
+    }
+}
+
+fn parse_event(line: &str) -> Option<TraceEvent> {
+    let mut words = line.split_whitespace();
+    let millis: f64 = words.next()?.parse().ok()?;
+    if !(millis >= 0.0 && millis.is_finite()) {
+        return None;
+    }
+    let time = Duration::from_secs_f64(millis / 1000.0);
+    let update = match words.next()? {
+        "viewer" => TraceUpdate::ViewerPose(parse_pose(&mut words)?),
+        "add-input" => {
+            let id = InputId(words.next()?.parse().ok()?);
+            let handedness = match words.next()? {
+                "none" => Handedness::None,
+                "left" => Handedness::Left,
+                "right" => Handedness::Right,
+                _ => return None,
+            };
+            let target_ray_mode = match words.next()? {
+                "gaze" => TargetRayMode::Gaze,
+                "tracked-pointer" => TargetRayMode::TrackedPointer,
+                "screen" => TargetRayMode::Screen,
+                "transient-pointer" => TargetRayMode::TransientPointer,
+                _ => return None,
+            };
+            let profiles = words.by_ref().map(String::from).collect();
+            TraceUpdate::AddInput(id, handedness, target_ray_mode, profiles)
+        }
+        "remove-input" => TraceUpdate::RemoveInput(InputId(words.next()?.parse().ok()?)),
+        "pointer" => {
+            let id = InputId(words.next()?.parse().ok()?);
+            TraceUpdate::PointerPose(id, parse_pose(&mut words)?)
+        }
+        "grip" => {
+            let id = InputId(words.next()?.parse().ok()?);
+            TraceUpdate::GripPose(id, parse_pose(&mut words)?)
+        }
+        "button" => {
+            let id = InputId(words.next()?.parse().ok()?);
+            let button_type = match words.next()? {
+                "grip" => MockButtonType::Grip,
+                "touchpad" => MockButtonType::Touchpad,
+                "thumbstick" => MockButtonType::Thumbstick,
+                "optional-button" => MockButtonType::OptionalButton,
+                "optional-thumbstick" => MockButtonType::OptionalThumbstick,
+                _ => return None,
+            };
+            let pressed = words.next()? == "1";
+            let touched = words.next()? == "1";
+            let mut values = words.by_ref().map(|word| word.parse::<f32>().ok());
+            let button = MockButton {
+                button_type,
+                pressed,
+                touched,
+                pressed_value: values.next()??,
+                x_value: values.next()??,
+                y_value: values.next()??,
+            };
+            TraceUpdate::Button(id, button)
+        }
+        "select" => {
+            let id = InputId(words.next()?.parse().ok()?);
+            let kind = match words.next()? {
+                "select" => SelectKind::Select,
+                "squeeze" => SelectKind::Squeeze,
+                _ => return None,
+            };
+            let event = match words.next()? {
+                "start" => SelectEvent::Start,
+                "end" => SelectEvent::End,
+                "select" => SelectEvent::Select,
+                _ => return None,
+            };
+            TraceUpdate::Select(id, kind, event)
+        }
+        _ => return None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    Some(TraceEvent { time, update })
+}
+
+/// Parse a pose, which is `none` or a position followed by an orientation
+fn parse_pose<'a, Src, Dst>(
+    words: &mut impl Iterator<Item = &'a str>,
+) -> Option<Option<RigidTransform3D<f32, Src, Dst>>> {
+    let mut values = [0.0; 7];
+    for (index, value) in values.iter_mut().enumerate() {
+        let word = words.next()?;
+        if index == 0 && word == "none" {
+            return Some(None);
+        }
+        *value = word.parse().ok()?;
+    }
+    let [x, y, z, i, j, k, r] = values;
+    let rotation = Rotation3D::quaternion(i, j, k, r);
+    let translation = Vector3D::new(x, y, z);
+    Some(Some(RigidTransform3D::new(rotation, translation)))
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            write!(f, "{}", event.time.as_secs_f64() * 1000.0)?;
+            match event.update {
+                TraceUpdate::ViewerPose(ref pose) => write!(f, " viewer {}", Pose(pose))?,
+                TraceUpdate::AddInput(id, handedness, target_ray_mode, ref profiles) => {
+                    let handedness = match handedness {
+                        Handedness::None => "none",
+                        Handedness::Left => "left",
+                        Handedness::Right => "right",
+                    };
+                    let target_ray_mode = match target_ray_mode {
+                        TargetRayMode::Gaze => "gaze",
+                        TargetRayMode::TrackedPointer => "tracked-pointer",
+                        TargetRayMode::Screen => "screen",
+                        TargetRayMode::TransientPointer => "transient-pointer",
+                    };
+                    write!(f, " add-input {} {} {}", id.0, handedness, target_ray_mode)?;
+                    for profile in profiles {
+                        write!(f, " {}", profile)?;
+                    }
+                }
+                TraceUpdate::RemoveInput(id) => write!(f, " remove-input {}", id.0)?,
+                TraceUpdate::PointerPose(id, ref pose) => {
+                    write!(f, " pointer {} {}", id.0, Pose(pose))?
+                }
+                TraceUpdate::GripPose(id, ref pose) => write!(f, " grip {} {}", id.0, Pose(pose))?,
+                TraceUpdate::Button(id, ref button) => {
+                    let button_type = match button.button_type {
+                        MockButtonType::Grip => "grip",
+                        MockButtonType::Touchpad => "touchpad",
+                        MockButtonType::Thumbstick => "thumbstick",
+                        MockButtonType::OptionalButton => "optional-button",
+                        MockButtonType::OptionalThumbstick => "optional-thumbstick",
+                    };
+                    write!(
+                        f,
+                        " button {} {} {} {} {} {} {}",
+                        id.0,
+                        button_type,
+                        button.pressed as u8,
+                        button.touched as u8,
+                        button.pressed_value,
+                        button.x_value,
+                        button.y_value
+                    )?
+                }
+                TraceUpdate::Select(id, kind, event) => {
+                    let kind = match kind {
+                        SelectKind::Select => "select",
+                        SelectKind::Squeeze => "squeeze",
+                    };
+                    let event = match event {
+                        SelectEvent::Start => "start",
+                        SelectEvent::End => "end",
+                        SelectEvent::Select => "select",
+                    };
+                    write!(f, " select {} {} {}", id.0, kind, event)?
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+struct Pose<'a, Src, Dst>(&'a Option<RigidTransform3D<f32, Src, Dst>>);
+
+impl<'a, Src, Dst> fmt::Display for Pose<'a, Src, Dst> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(pose) => {
+                let (position, orientation) = (pose.translation, pose.rotation);
+                write!(
+                    f,
+                    "{} {} {} {} {} {} {}",
+                    position.x,
+                    position.y,
+                    position.z,
+                    orientation.i,
+                    orientation.j,
+                    orientation.k,
+                    orientation.r
+                )
+            }
+            None => write!(f, "none"),
+        }
+    }
+}
+
+/// Records the frames of a session into a trace that can be played back by a `ReplayDiscovery`.
+/// Input sources that weren't recorded with `record_input_source` are recorded as
+/// right-handed tracked pointers, and presses and squeezes as select events, since
+/// frames don't carry buttons' types.
+#[derive(Default)]
+pub struct TraceRecorder {
+    /// The predicted display time of the first frame, which is the start of the trace
+    start: Option<f64>,
+    trace: Trace,
+    /// The input sources of the session
+    sources: HashMap<InputId, InputSource>,
+    /// Whether each input source is pressed and squeezed
+    inputs: HashMap<InputId, (bool, bool)>,
+}
+
+impl TraceRecorder {
+    pub fn new() -> TraceRecorder {
+        TraceRecorder::default()
+    }
+
+    /// Record an input source, such as one of the session's initial inputs or the source
+    /// of an `AddInput` event, before the first frame it is in, so that it is played back
+    /// with the same handedness, target ray mode and profiles
+    pub fn record_input_source(&mut self, source: &InputSource) {
+        self.sources.insert(source.id, source.clone());
+    }
+
+    /// Frames are recorded at their predicted display times, so traces don't depend
+    /// on how long the frames took to render
+    pub fn record_frame(&mut self, frame: &Frame) {
+        let start = *self.start.get_or_insert(frame.predicted_display_time);
+        let nanos = (frame.predicted_display_time - start).max(0.0);
+        let time = Duration::from_nanos(nanos as u64);
+        let mut updates = vec![];
+        let viewer = frame.pose.as_ref().map(|pose| pose.transform);
+        updates.push(TraceUpdate::ViewerPose(viewer));
+
+        for input in &frame.inputs {
+            let id = input.id;
+            let (pressed, squeezed) = match self.inputs.get(&id) {
+                Some(&state) => state,
+                None => {
+                    let update = match self.sources.get(&id) {
+                        Some(source) => TraceUpdate::AddInput(
+                            id,
+                            source.handedness,
+                            source.target_ray_mode,
+                            source.profiles.clone(),
+                        ),
+                        None => TraceUpdate::AddInput(
+                            id,
+                            Handedness::Right,
+                            TargetRayMode::TrackedPointer,
+                            vec![],
+                        ),
+                    };
+                    updates.push(update);
+                    (false, false)
+                }
+            };
+            updates.push(TraceUpdate::PointerPose(id, input.target_ray_origin));
+            updates.push(TraceUpdate::GripPose(id, input.grip_origin));
+            for &(kind, was_down, down) in &[
+                (SelectKind::Select, pressed, input.pressed),
+                (SelectKind::Squeeze, squeezed, input.squeezed),
+            ] {
+                if down && !was_down {
+                    updates.push(TraceUpdate::Select(id, kind, SelectEvent::Start));
+                } else if was_down && !down {
+                    updates.push(TraceUpdate::Select(id, kind, SelectEvent::Select));
+                }
+            }
+            self.inputs.insert(id, (input.pressed, input.squeezed));
+        }
+
+        let removed: Vec<_> = self
+            .inputs
+            .keys()
+            .copied()
+            .filter(|id| !frame.inputs.iter().any(|input| input.id == *id))
+            .collect();
+        for id in removed {
+            self.inputs.remove(&id);
+            updates.push(TraceUpdate::RemoveInput(id));
+        }
+
+        let events = updates
+            .into_iter()
+            .map(|update| TraceEvent { time, update });
+        self.trace.events.extend(events);
+    }
+
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webxr_api::InputFrame;
+
+    const TRACE: &str = "\
+0 viewer 0 1.6 0 0 0 0 1
+0 add-input 0 right tracked-pointer oculus-touch generic-trigger
+16 pointer 0 0.25 1.2 -0.3 0 0.70710677 0 0.70710677
+16 grip 0 none
+33 button 0 touchpad 1 0 0.5 -1 0.75
+50 select 0 squeeze start
+67 remove-input 0
+";
+
+    /// The time and the rest of each line of a trace
+    fn lines(text: &str) -> Vec<(f64, String)> {
+        text.lines()
+            .map(|line| {
+                let (time, rest) = line.split_once(' ').unwrap();
+                (time.parse().unwrap(), rest.to_owned())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        let trace = Trace::parse(TRACE).unwrap();
+        assert_eq!(trace.events.len(), 7);
+        let displayed = trace.to_string();
+        let expected = lines(TRACE);
+        let actual = lines(&displayed);
+        assert_eq!(actual.len(), expected.len());
+        for ((expected_time, expected), (actual_time, actual)) in expected.iter().zip(&actual) {
+            assert!((expected_time - actual_time).abs() < 1e-3);
+            assert_eq!(expected, actual);
+        }
+        let reparsed = Trace::parse(&displayed).unwrap();
+        assert_eq!(reparsed.to_string(), displayed);
+    }
+
+    #[test]
+    fn parse_reads_updates() {
+        let trace = Trace::parse(TRACE).unwrap();
+        match trace.events[0].update {
+            TraceUpdate::ViewerPose(Some(pose)) => assert_eq!(pose.translation.y, 1.6),
+            ref update => panic!("Unexpected update {:?}", update),
+        }
+        match trace.events[1].update {
+            TraceUpdate::AddInput(InputId(0), Handedness::Right, _, ref profiles) => {
+                assert_eq!(profiles, &["oculus-touch", "generic-trigger"])
+            }
+            ref update => panic!("Unexpected update {:?}", update),
+        }
+        match trace.events[3].update {
+            TraceUpdate::GripPose(InputId(0), None) => (),
+            ref update => panic!("Unexpected update {:?}", update),
+        }
+        match trace.events[4].update {
+            TraceUpdate::Button(InputId(0), ref button) => {
+                assert_eq!(button.button_type, MockButtonType::Touchpad);
+                assert!(button.pressed);
+                assert!(!button.touched);
+                assert_eq!(
+                    (button.pressed_value, button.x_value, button.y_value),
+                    (0.5, -1.0, 0.75)
+                );
+            }
+            ref update => panic!("Unexpected update {:?}", update),
+        }
+        assert!((trace.events[6].time.as_secs_f64() - 0.067).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parse_skips_comments_and_sorts_by_time() {
+        let text = "\
+# A comment
+
+20 remove-input 1
+10 add-input 1 left gaze
+";
+        let trace = Trace::parse(text).unwrap();
+        assert_eq!(trace.events.len(), 2);
+        assert!(matches!(
+            trace.events[0].update,
+            TraceUpdate::AddInput(InputId(1), Handedness::Left, TargetRayMode::Gaze, _)
+        ));
+        assert!(matches!(
+            trace.events[1].update,
+            TraceUpdate::RemoveInput(InputId(1))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_lines() {
+        for line in &[
+            "-1 remove-input 0",
+            "NaN remove-input 0",
+            "0 teleport 0",
+            "0 remove-input 0 1",
+            "0 viewer 0 1.6 0",
+            "0 add-input 0 middle gaze",
+            "0 button 0 touchpad 1 1 1 0",
+            "0 select 0 select maybe",
+        ] {
+            assert!(Trace::parse(line).is_err(), "{} was parsed", line);
+        }
+    }
+
+    /// Whether each input source added by the updates supports grip
+    fn supports_grip(updates: &[(Duration, MockDeviceMsg)]) -> Vec<(InputId, bool)> {
+        updates
+            .iter()
+            .filter_map(|(_, msg)| match msg {
+                MockDeviceMsg::AddInputSource(init) => {
+                    Some((init.source.id, init.source.supports_grip))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inputs_support_grip_if_a_grip_pose_was_recorded() {
+        let text = "\
+0 add-input 0 left tracked-pointer
+0 add-input 1 right tracked-pointer
+10 grip 0 0 1 0 0 0 0 1
+10 grip 1 none
+20 remove-input 0
+30 add-input 0 left gaze
+40 grip 1 0 1 0 0 0 0 1
+";
+        let updates = Trace::parse(text).unwrap().into_updates();
+        assert_eq!(
+            supports_grip(&updates),
+            [(InputId(0), true), (InputId(1), true), (InputId(0), false)]
+        );
+    }
+
+    #[test]
+    fn recorder_records_input_sources() {
+        let source = InputSource {
+            handedness: Handedness::Left,
+            target_ray_mode: TargetRayMode::Screen,
+            id: InputId(3),
+            supports_grip: false,
+            hand_support: None,
+            profiles: vec!["generic-touchscreen".into()],
+        };
+        let input = |id, pressed| InputFrame {
+            id,
+            target_ray_origin: None,
+            grip_origin: None,
+            pressed,
+            hand: None,
+            squeezed: false,
+            button_values: vec![],
+            axis_values: vec![],
+            input_changed: false,
+        };
+        let frame = |time, inputs| Frame {
+            pose: None,
+            inputs,
+            events: vec![],
+            sub_images: vec![],
+            hit_test_results: vec![],
+            transient_hit_test_results: vec![],
+            anchor_poses: vec![],
+            detected_planes: vec![],
+            mesh_updates: vec![],
+            light_estimate: None,
+            predicted_display_time: time,
+        };
+
+        let mut recorder = TraceRecorder::new();
+        recorder.record_input_source(&source);
+        recorder.record_frame(&frame(
+            1e9,
+            vec![input(InputId(3), true), input(InputId(4), false)],
+        ));
+        recorder.record_frame(&frame(1e9 + 16e6, vec![]));
+        let events = &recorder.trace().events;
+
+        let added: Vec<_> = events
+            .iter()
+            .filter(|event| matches!(event.update, TraceUpdate::AddInput(..)))
+            .collect();
+        assert_eq!(added.len(), 2);
+        match added[0].update {
+            TraceUpdate::AddInput(
+                InputId(3),
+                Handedness::Left,
+                TargetRayMode::Screen,
+                ref profiles,
+            ) => {
+                assert_eq!(profiles, &["generic-touchscreen"])
+            }
+            ref update => panic!("Unexpected update {:?}", update),
+        }
+        match added[1].update {
+            TraceUpdate::AddInput(
+                InputId(4),
+                Handedness::Right,
+                TargetRayMode::TrackedPointer,
+                ref profiles,
+            ) => {
+                assert!(profiles.is_empty())
+            }
+            ref update => panic!("Unexpected update {:?}", update),
+        }
+        assert!(events.iter().any(|event| matches!(
+            event.update,
+            TraceUpdate::Select(InputId(3), SelectKind::Select, SelectEvent::Start)
+        )));
+        let removed = events
+            .iter()
+            .filter(|event| matches!(event.update, TraceUpdate::RemoveInput(_)))
+            .count();
+        assert_eq!(removed, 2);
+        assert_eq!(events.last().unwrap().time, Duration::from_millis(16));
+    }
+}
@@ -4,16 +4,19 @@
 
 //! An implementation of layer management using surfman
 
-use crate::gl_utils::GlClearer;
+use crate::gl_utils::{
+    clear_framebuffer, create_texture, depth_stencil_attachment, framebuffer,
+    supported_depth_stencil_format, DepthPacker, GlClearer, TextureFormat, RGBA8,
+};
 use euclid::{Point2D, Rect, Size2D};
-use glow::{self as gl, Context as Gl, HasContext, PixelUnpackData};
+use glow::{self as gl, Context as Gl, HasContext, PixelPackData, PixelUnpackData};
 use std::collections::HashMap;
 use std::num::NonZeroU32;
 use surfman::chains::{PreserveBuffer, SwapChains, SwapChainsAPI};
 use surfman::{Context as SurfmanContext, Device as SurfmanDevice, SurfaceAccess, SurfaceTexture};
 use webxr_api::{
-    ContextId, Error, GLContexts, GLTypes, LayerId, LayerInit, LayerManagerAPI, SubImage,
-    SubImages, Viewports,
+    scale_viewport, CameraImage, ContextId, DepthStencilFormat, Error, GLContexts, GLTypes,
+    LayerId, LayerImage, LayerInit, LayerManagerAPI, SubImage, SubImages, Viewport, Viewports,
 };
 
 #[derive(Copy, Clone, Debug)]
@@ -25,31 +28,87 @@ impl GLTypes for SurfmanGL {
     type Bindings = Gl;
 }
 
+// The textures of a layer backed by texture arrays, whose slices are copied
+// into the layer's swap chain surface at the end of each frame. Surfman surfaces
+// are the only textures shared with the device's compositor, and they can't be
+// arrays, so this copy is how each view's slice reaches the compositor.
+#[derive(Clone, Copy)]
+struct TextureArray {
+    color: gl::NativeTexture,
+    depth_stencil: Option<(gl::NativeTexture, DepthStencilFormat)>,
+    size: Size2D<i32, Viewport>,
+    len: usize,
+}
+
+// The number of samples to use for antialiased layers, if the GL supports it
+const MULTISAMPLE_SAMPLES: i32 = 4;
+
+// The multisampled buffers of an antialiased layer, which are resolved into
+// its color and depth/stencil textures at the end of each frame
+#[derive(Clone, Copy)]
+struct Multisample {
+    framebuffer: gl::NativeFramebuffer,
+    color: gl::NativeRenderbuffer,
+    depth_stencil: Option<(gl::NativeRenderbuffer, DepthStencilFormat)>,
+    size: Size2D<i32, Viewport>,
+}
+
 pub struct SurfmanLayerManager {
     layers: Vec<(ContextId, LayerId)>,
     swap_chains: SwapChains<LayerId, SurfmanDevice>,
+    /// Swap chains for the packed depth of layers that submit depth,
+    /// if the device composites by depth
+    depth_swap_chains: Option<SwapChains<LayerId, SurfmanDevice>>,
     surface_textures: HashMap<LayerId, SurfaceTexture>,
-    depth_stencil_textures: HashMap<LayerId, Option<gl::NativeTexture>>,
+    depth_stencil_textures: HashMap<LayerId, (gl::NativeTexture, DepthStencilFormat)>,
+    texture_arrays: HashMap<LayerId, TextureArray>,
+    multisamples: HashMap<LayerId, Multisample>,
+    camera_textures: HashMap<ContextId, gl::NativeTexture>,
+    layer_inits: HashMap<LayerId, LayerInit>,
     viewports: Viewports,
+    viewport_scale: f32,
+    /// The viewport scale of projection layers in the current frame
+    frame_viewport_scale: f32,
+    /// Whether submitted color textures are read back, for example for testing
+    read_back: bool,
+    read_back_images: HashMap<LayerId, LayerImage>,
     clearer: GlClearer,
+    depth_packer: DepthPacker,
 }
 
 impl SurfmanLayerManager {
     pub fn new(
         viewports: Viewports,
         swap_chains: SwapChains<LayerId, SurfmanDevice>,
+        depth_swap_chains: Option<SwapChains<LayerId, SurfmanDevice>>,
+        read_back: bool,
     ) -> SurfmanLayerManager {
         let layers = Vec::new();
         let surface_textures = HashMap::new();
         let depth_stencil_textures = HashMap::new();
+        let texture_arrays = HashMap::new();
+        let multisamples = HashMap::new();
+        let camera_textures = HashMap::new();
+        let layer_inits = HashMap::new();
         let clearer = GlClearer::new(false);
+        let depth_packer = DepthPacker::new();
         SurfmanLayerManager {
             layers,
             swap_chains,
+            depth_swap_chains,
             surface_textures,
             depth_stencil_textures,
+            texture_arrays,
+            multisamples,
+            camera_textures,
+            layer_inits,
             viewports,
+            viewport_scale: 1.0,
+            frame_viewport_scale: 1.0,
+            read_back,
+            read_back_images: HashMap::new(),
             clearer,
+            depth_packer,
         }
     }
 }
@@ -66,32 +125,55 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         let layer_id = LayerId::new();
         let access = SurfaceAccess::GPUOnly;
         let size = texture_size.to_untyped();
-        // TODO: Treat depth and stencil separately?
-        let has_depth_stencil = match init {
-            LayerInit::WebGLLayer { stencil, depth, .. } => stencil | depth,
-            LayerInit::ProjectionLayer { stencil, depth, .. } => stencil | depth,
+        let depth_stencil_format = match init.depth_stencil_format() {
+            Some(format) => {
+                let gl = contexts
+                    .bindings(device, context_id)
+                    .ok_or(Error::NoMatchingDevice)?;
+                Some(supported_depth_stencil_format(gl, format))
+            }
+            None => None,
         };
-        if has_depth_stencil {
+        if let Some((size, len)) = init.texture_array(&self.viewports) {
             let gl = contexts
                 .bindings(device, context_id)
                 .ok_or(Error::NoMatchingDevice)?;
-            let depth_stencil_texture = unsafe { gl.create_texture().ok() };
-            unsafe {
-                gl.bind_texture(gl::TEXTURE_2D, depth_stencil_texture);
-                gl.tex_image_2d(
-                    gl::TEXTURE_2D,
-                    0,
-                    gl::DEPTH24_STENCIL8 as _,
-                    size.width,
-                    size.height,
-                    0,
-                    gl::DEPTH_STENCIL,
-                    gl::UNSIGNED_INT_24_8,
-                    PixelUnpackData::Slice(None),
-                );
-            }
+            let color = unsafe { create_texture(gl, RGBA8, size.width, size.height, Some(len))? };
+            let depth_stencil = match depth_stencil_format {
+                Some(format) => {
+                    let texture_format = TextureFormat::depth_stencil(format);
+                    let texture = unsafe {
+                        create_texture(gl, texture_format, size.width, size.height, Some(len))?
+                    };
+                    Some((texture, format))
+                }
+                None => None,
+            };
+            let texture_array = TextureArray {
+                color,
+                depth_stencil,
+                size,
+                len,
+            };
+            self.texture_arrays.insert(layer_id, texture_array);
+        } else if let Some(format) = depth_stencil_format {
+            let gl = contexts
+                .bindings(device, context_id)
+                .ok_or(Error::NoMatchingDevice)?;
+            let texture_format = TextureFormat::depth_stencil(format);
+            let depth_stencil_texture =
+                unsafe { create_texture(gl, texture_format, size.width, size.height, None)? };
             self.depth_stencil_textures
-                .insert(layer_id, depth_stencil_texture);
+                .insert(layer_id, (depth_stencil_texture, format));
+        }
+        // TODO: multisampled texture arrays
+        if init.antialias() && init.texture_array(&self.viewports).is_none() {
+            let gl = contexts
+                .bindings(device, context_id)
+                .ok_or(Error::NoMatchingDevice)?;
+            let multisample =
+                unsafe { create_multisample(gl, depth_stencil_format, texture_size)? };
+            self.multisamples.insert(layer_id, multisample);
         }
         let context = contexts
             .context(device, context_id)
@@ -99,7 +181,13 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         self.swap_chains
             .create_detached_swap_chain(layer_id, size, device, context, access)
             .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+        if let (Some(depth_swap_chains), true) = (&self.depth_swap_chains, init.submits_depth()) {
+            depth_swap_chains
+                .create_detached_swap_chain(layer_id, size, device, context, access)
+                .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+        }
         self.layers.push((context_id, layer_id));
+        self.layer_inits.insert(layer_id, init);
         Ok(layer_id)
     }
 
@@ -118,14 +206,50 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         };
         self.layers.retain(|&ids| ids != (context_id, layer_id));
         let _ = self.swap_chains.destroy(layer_id, device, context);
+        if let Some(ref depth_swap_chains) = self.depth_swap_chains {
+            let _ = depth_swap_chains.destroy(layer_id, device, context);
+        }
         self.surface_textures.remove(&layer_id);
-        if let Some(depth_stencil_texture) = self.depth_stencil_textures.remove(&layer_id) {
+        self.layer_inits.remove(&layer_id);
+        self.read_back_images.remove(&layer_id);
+        if let Some((depth_stencil_texture, _)) = self.depth_stencil_textures.remove(&layer_id) {
+            let gl = contexts.bindings(device, context_id).unwrap();
+            unsafe {
+                gl.delete_texture(depth_stencil_texture);
+            }
+        }
+        if let Some(multisample) = self.multisamples.remove(&layer_id) {
+            let gl = contexts.bindings(device, context_id).unwrap();
+            unsafe {
+                gl.delete_framebuffer(multisample.framebuffer);
+                gl.delete_renderbuffer(multisample.color);
+                if let Some((depth_stencil, _)) = multisample.depth_stencil {
+                    gl.delete_renderbuffer(depth_stencil);
+                }
+            }
+        }
+        if let Some(texture_array) = self.texture_arrays.remove(&layer_id) {
             let gl = contexts.bindings(device, context_id).unwrap();
-            if let Some(depth_stencil_texture) = depth_stencil_texture {
+            unsafe {
+                gl.delete_texture(texture_array.color);
+                if let Some((depth_stencil, _)) = texture_array.depth_stencil {
+                    gl.delete_texture(depth_stencil);
+                }
+            }
+        }
+        if !self
+            .layers
+            .iter()
+            .any(|&(other_id, _)| other_id == context_id)
+        {
+            if let Some(camera_texture) = self.camera_textures.remove(&context_id) {
+                let gl = contexts.bindings(device, context_id).unwrap();
                 unsafe {
-                    gl.delete_texture(depth_stencil_texture);
+                    gl.delete_texture(camera_texture);
                 }
             }
+            self.depth_packer
+                .destroy_context(device, contexts, context_id);
         }
     }
 
@@ -139,6 +263,7 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         contexts: &mut dyn GLContexts<SurfmanGL>,
         layers: &[(ContextId, LayerId)],
     ) -> Result<Vec<SubImages>, Error> {
+        self.frame_viewport_scale = self.viewport_scale;
         layers
             .iter()
             .map(|&(context_id, layer_id)| {
@@ -155,31 +280,78 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
                     .map_err(|_| Error::NoMatchingDevice)?;
                 let color_texture = device.surface_texture_object(&surface_texture);
                 let color_target = device.surface_gl_texture_target();
-                let depth_stencil_texture = self
-                    .depth_stencil_textures
-                    .get(&layer_id)
-                    .cloned()
-                    .flatten();
+                let depth_stencil = self.depth_stencil_textures.get(&layer_id).copied();
+                let depth_stencil_texture = depth_stencil.map(|(texture, _)| texture.0.get());
+                let depth_stencil_format = depth_stencil.map(|(_, format)| format);
                 let texture_array_index = None;
+                let viewport_scale = match self.layer_inits.get(&layer_id) {
+                    Some(init) if init.is_projection() => self.frame_viewport_scale,
+                    _ => 1.0,
+                };
+                let multisample = self.multisamples.get(&layer_id).copied();
+                let multisample_framebuffer =
+                    multisample.map(|multisample| multisample.framebuffer.0.get());
                 let origin = Point2D::new(0, 0);
                 let sub_image = Some(SubImage {
                     color_texture,
-                    depth_stencil_texture: depth_stencil_texture.map(|nt| nt.0.get()),
+                    depth_stencil_texture,
+                    depth_stencil_format,
                     texture_array_index,
                     viewport: Rect::new(origin, surface_size),
+                    multisample_framebuffer,
                 });
-                let view_sub_images = self
-                    .viewports
-                    .viewports
-                    .iter()
-                    .map(|&viewport| SubImage {
+                self.surface_textures.insert(layer_id, surface_texture);
+                if let Some(texture_array) = self.texture_arrays.get(&layer_id).copied() {
+                    // Each view renders to its own slice, and there is no single sub image
+                    let color_texture = texture_array.color.0.get();
+                    let depth_stencil = texture_array.depth_stencil;
+                    let view_sub_images = (0..texture_array.len)
+                        .map(|index| SubImage {
+                            color_texture,
+                            depth_stencil_texture: depth_stencil
+                                .map(|(texture, _)| texture.0.get()),
+                            depth_stencil_format: depth_stencil.map(|(_, format)| format),
+                            texture_array_index: Some(index as u32),
+                            viewport: scale_viewport(
+                                Rect::new(origin, texture_array.size),
+                                viewport_scale,
+                            ),
+                            multisample_framebuffer: None,
+                        })
+                        .collect();
+                    for index in 0..texture_array.len {
+                        self.clearer.clear(
+                            device,
+                            contexts,
+                            context_id,
+                            layer_id,
+                            Some(texture_array.color),
+                            gl::TEXTURE_2D_ARRAY,
+                            depth_stencil,
+                            Some(index as u32),
+                        );
+                    }
+                    return Ok(SubImages {
+                        layer_id,
+                        sub_image: None,
+                        view_sub_images,
+                    });
+                }
+                let view_sub_image_rects = match self.layer_inits.get(&layer_id) {
+                    Some(init) => init.view_sub_image_rects(&self.viewports),
+                    None => self.viewports.viewports.clone(),
+                };
+                let view_sub_images = view_sub_image_rects
+                    .into_iter()
+                    .map(|viewport| SubImage {
                         color_texture,
-                        depth_stencil_texture: depth_stencil_texture.map(|texture| texture.0.get()),
+                        depth_stencil_texture,
+                        depth_stencil_format,
                         texture_array_index,
-                        viewport,
+                        viewport: scale_viewport(viewport, viewport_scale),
+                        multisample_framebuffer,
                     })
                     .collect();
-                self.surface_textures.insert(layer_id, surface_texture);
                 self.clearer.clear(
                     device,
                     contexts,
@@ -187,8 +359,14 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
                     layer_id,
                     NonZeroU32::new(color_texture).map(gl::NativeTexture),
                     color_target,
-                    depth_stencil_texture,
+                    depth_stencil,
+                    None,
                 );
+                if let Some(multisample) = multisample {
+                    if let Some(gl) = contexts.bindings(device, context_id) {
+                        clear_framebuffer(gl, Some(multisample.framebuffer));
+                    }
+                }
                 Ok(SubImages {
                     layer_id,
                     sub_image,
@@ -205,9 +383,103 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         layers: &[(ContextId, LayerId)],
     ) -> Result<(), Error> {
         for &(context_id, layer_id) in layers {
+            // Resolve antialiased layers first, so their depth can be submitted
+            if let (Some(multisample), Some(surface_texture)) = (
+                self.multisamples.get(&layer_id),
+                self.surface_textures.get(&layer_id),
+            ) {
+                let texture = device.surface_texture_object(surface_texture);
+                let target = device.surface_gl_texture_target();
+                let depth_stencil = self.depth_stencil_textures.get(&layer_id).copied();
+                let gl = contexts
+                    .bindings(device, context_id)
+                    .ok_or(Error::NoMatchingDevice)?;
+                unsafe {
+                    resolve_multisample(
+                        gl,
+                        multisample,
+                        NonZeroU32::new(texture).map(gl::NativeTexture),
+                        target,
+                        depth_stencil,
+                    );
+                }
+            }
+            let depth_swap_chain = self
+                .depth_swap_chains
+                .as_ref()
+                .and_then(|depth_swap_chains| depth_swap_chains.get(layer_id));
+            let depth_texture = self
+                .depth_stencil_textures
+                .get(&layer_id)
+                .map(|&(texture, _)| texture);
+            if let (Some(depth_swap_chain), Some(depth_texture)) = (depth_swap_chain, depth_texture)
+            {
+                let context = contexts
+                    .context(device, context_id)
+                    .ok_or(Error::NoMatchingDevice)?;
+                let surface_texture = depth_swap_chain
+                    .take_surface_texture(device, context)
+                    .map_err(|_| Error::NoMatchingDevice)?;
+                let texture = device.surface_texture_object(&surface_texture);
+                let target = device.surface_gl_texture_target();
+                let packed = self.depth_packer.pack(
+                    device,
+                    contexts,
+                    context_id,
+                    depth_texture,
+                    NonZeroU32::new(texture).map(gl::NativeTexture),
+                    target,
+                    depth_swap_chain.size(),
+                );
+                let context = contexts
+                    .context(device, context_id)
+                    .ok_or(Error::NoMatchingDevice)?;
+                depth_swap_chain
+                    .recycle_surface_texture(device, context, surface_texture)
+                    .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+                packed?;
+                depth_swap_chain
+                    .swap_buffers(device, context, PreserveBuffer::No)
+                    .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+            }
             let gl = contexts
                 .bindings(device, context_id)
                 .ok_or(Error::NoMatchingDevice)?;
+            if let (Some(texture_array), Some(surface_texture)) = (
+                self.texture_arrays.get(&layer_id),
+                self.surface_textures.get(&layer_id),
+            ) {
+                let texture = device.surface_texture_object(surface_texture);
+                let target = device.surface_gl_texture_target();
+                unsafe {
+                    copy_texture_array(
+                        gl,
+                        texture_array,
+                        NonZeroU32::new(texture).map(gl::NativeTexture),
+                        target,
+                        &self.viewports,
+                        self.frame_viewport_scale,
+                    );
+                }
+            }
+            if let (true, Some(surface_texture), Some(swap_chain)) = (
+                self.read_back,
+                self.surface_textures.get(&layer_id),
+                self.swap_chains.get(layer_id),
+            ) {
+                let texture = device.surface_texture_object(surface_texture);
+                let target = device.surface_gl_texture_target();
+                let size = swap_chain.size();
+                let image = unsafe {
+                    read_texture(
+                        gl,
+                        NonZeroU32::new(texture).map(gl::NativeTexture),
+                        target,
+                        Size2D::from_untyped(size),
+                    )
+                };
+                self.read_back_images.insert(layer_id, image);
+            }
             unsafe {
                 gl.flush();
             }
@@ -231,4 +503,341 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         }
         Ok(())
     }
+
+    fn upload_camera_image(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        image: &CameraImage,
+    ) -> Result<u32, Error> {
+        let expected_len = (image.width as usize)
+            .checked_mul(image.height as usize)
+            .and_then(|pixels| pixels.checked_mul(4));
+        if expected_len != Some(image.data.len()) {
+            return Err(Error::BackendSpecific(format!(
+                "Camera image of {}x{} has {} bytes of data",
+                image.width,
+                image.height,
+                image.data.len()
+            )));
+        }
+        let gl = contexts
+            .bindings(device, context_id)
+            .ok_or(Error::NoMatchingDevice)?;
+        let texture = match self.camera_textures.get(&context_id) {
+            Some(&texture) => texture,
+            None => {
+                let texture = unsafe { gl.create_texture() }.map_err(Error::BackendSpecific)?;
+                self.camera_textures.insert(context_id, texture);
+                texture
+            }
+        };
+        unsafe {
+            let mut bound_texture = [0];
+            gl.get_parameter_i32_slice(gl::TEXTURE_BINDING_2D, &mut bound_texture);
+            gl.bind_texture(gl::TEXTURE_2D, Some(texture));
+            gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
+            gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
+            gl.tex_image_2d(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as _,
+                image.width as _,
+                image.height as _,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                PixelUnpackData::Slice(Some(&image.data)),
+            );
+            let bound_texture = NonZeroU32::new(bound_texture[0] as u32).map(gl::NativeTexture);
+            gl.bind_texture(gl::TEXTURE_2D, bound_texture);
+        }
+        Ok(texture.0.get())
+    }
+
+    fn read_pixels(
+        &mut self,
+        _device: &mut SurfmanDevice,
+        _contexts: &mut dyn GLContexts<SurfmanGL>,
+        _context_id: ContextId,
+        layer_id: LayerId,
+    ) -> Result<LayerImage, Error> {
+        if !self.read_back {
+            return Err(Error::UnsupportedFeature("read-pixels".into()));
+        }
+        self.read_back_images
+            .get(&layer_id)
+            .cloned()
+            .ok_or(Error::NoMatchingDevice)
+    }
+
+    fn set_viewport_scale(&mut self, scale: f32) {
+        self.viewport_scale = scale;
+    }
+
+    fn set_read_back(&mut self, read_back: bool) {
+        self.read_back = read_back;
+        if !read_back {
+            self.read_back_images.clear();
+        }
+    }
+}
+
+/// Copy each slice of a texture array into its view's viewport of a texture.
+/// Only the part of each slice and viewport that the viewport scale keeps is copied.
+unsafe fn copy_texture_array(
+    gl: &Gl,
+    texture_array: &TextureArray,
+    texture: Option<gl::NativeTexture>,
+    texture_target: u32,
+    viewports: &Viewports,
+    viewport_scale: f32,
+) {
+    // Save the current GL state
+    let mut bound_fbos = [0, 0];
+    let scissor_enabled = gl.is_enabled(gl::SCISSOR_TEST);
+    gl.get_parameter_i32_slice(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_fbos[0..]);
+    gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbos[1..]);
+
+    // Blit each slice
+    let read_fbo = gl.create_framebuffer().ok();
+    let draw_fbo = gl.create_framebuffer().ok();
+    gl.disable(gl::SCISSOR_TEST);
+    gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, draw_fbo);
+    gl.framebuffer_texture_2d(
+        gl::DRAW_FRAMEBUFFER,
+        gl::COLOR_ATTACHMENT0,
+        texture_target,
+        texture,
+        0,
+    );
+    gl.bind_framebuffer(gl::READ_FRAMEBUFFER, read_fbo);
+    let slice = scale_viewport(Rect::from_size(texture_array.size), viewport_scale);
+    for (index, viewport) in viewports
+        .viewports
+        .iter()
+        .enumerate()
+        .take(texture_array.len)
+    {
+        gl.framebuffer_texture_layer(
+            gl::READ_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            Some(texture_array.color),
+            0,
+            index as i32,
+        );
+        let viewport = scale_viewport(*viewport, viewport_scale);
+        gl.blit_framebuffer(
+            slice.min_x(),
+            slice.min_y(),
+            slice.max_x(),
+            slice.max_y(),
+            viewport.min_x(),
+            viewport.min_y(),
+            viewport.max_x(),
+            viewport.max_y(),
+            gl::COLOR_BUFFER_BIT,
+            gl::LINEAR,
+        );
+    }
+
+    // Restore the GL state
+    gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer(bound_fbos[0] as _));
+    gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbos[1] as _));
+    if scissor_enabled {
+        gl.enable(gl::SCISSOR_TEST);
+    }
+    if let Some(fbo) = read_fbo {
+        gl.delete_framebuffer(fbo);
+    }
+    if let Some(fbo) = draw_fbo {
+        gl.delete_framebuffer(fbo);
+    }
+    debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+}
+
+/// Allocate multisampled buffers for an antialiased layer
+unsafe fn create_multisample(
+    gl: &Gl,
+    depth_stencil_format: Option<DepthStencilFormat>,
+    size: Size2D<i32, Viewport>,
+) -> Result<Multisample, Error> {
+    let samples = MULTISAMPLE_SAMPLES.min(gl.get_parameter_i32(gl::MAX_SAMPLES));
+    let create_renderbuffer = |internal_format: u32| -> Result<gl::NativeRenderbuffer, Error> {
+        let renderbuffer = gl.create_renderbuffer().map_err(Error::BackendSpecific)?;
+        gl.bind_renderbuffer(gl::RENDERBUFFER, Some(renderbuffer));
+        gl.renderbuffer_storage_multisample(
+            gl::RENDERBUFFER,
+            samples,
+            internal_format,
+            size.width,
+            size.height,
+        );
+        Ok(renderbuffer)
+    };
+
+    // Save the current GL state
+    let mut bound_fbos = [0, 0];
+    let mut bound_renderbuffer = [0];
+    gl.get_parameter_i32_slice(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_fbos[0..]);
+    gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbos[1..]);
+    gl.get_parameter_i32_slice(gl::RENDERBUFFER_BINDING, &mut bound_renderbuffer);
+
+    // Allocate the buffers and attach them to a new FBO
+    let color = create_renderbuffer(RGBA8.internal_format)?;
+    let depth_stencil = match depth_stencil_format {
+        Some(format) => Some((
+            create_renderbuffer(TextureFormat::depth_stencil(format).internal_format)?,
+            format,
+        )),
+        None => None,
+    };
+    let fbo = gl.create_framebuffer().map_err(Error::BackendSpecific)?;
+    gl.bind_framebuffer(gl::FRAMEBUFFER, Some(fbo));
+    gl.framebuffer_renderbuffer(
+        gl::FRAMEBUFFER,
+        gl::COLOR_ATTACHMENT0,
+        gl::RENDERBUFFER,
+        Some(color),
+    );
+    if let Some((renderbuffer, format)) = depth_stencil {
+        gl.framebuffer_renderbuffer(
+            gl::FRAMEBUFFER,
+            depth_stencil_attachment(format),
+            gl::RENDERBUFFER,
+            Some(renderbuffer),
+        );
+    }
+
+    // Restore the GL state
+    gl.bind_renderbuffer(
+        gl::RENDERBUFFER,
+        NonZeroU32::new(bound_renderbuffer[0] as u32).map(gl::NativeRenderbuffer),
+    );
+    gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer(bound_fbos[0] as _));
+    gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbos[1] as _));
+    debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+
+    Ok(Multisample {
+        framebuffer: fbo,
+        color,
+        depth_stencil,
+        size,
+    })
+}
+
+/// Resolve a layer's multisampled buffers into its color and depth/stencil textures
+unsafe fn resolve_multisample(
+    gl: &Gl,
+    multisample: &Multisample,
+    texture: Option<gl::NativeTexture>,
+    texture_target: u32,
+    depth_stencil: Option<(gl::NativeTexture, DepthStencilFormat)>,
+) {
+    let size = multisample.size;
+
+    // Save the current GL state
+    let mut bound_fbos = [0, 0];
+    let scissor_enabled = gl.is_enabled(gl::SCISSOR_TEST);
+    gl.get_parameter_i32_slice(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_fbos[0..]);
+    gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbos[1..]);
+
+    // Blit the multisampled buffers into the textures
+    let resolve_fbo = gl.create_framebuffer().ok();
+    gl.disable(gl::SCISSOR_TEST);
+    gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, resolve_fbo);
+    gl.framebuffer_texture_2d(
+        gl::DRAW_FRAMEBUFFER,
+        gl::COLOR_ATTACHMENT0,
+        texture_target,
+        texture,
+        0,
+    );
+    let mut mask = gl::COLOR_BUFFER_BIT;
+    if let Some((depth_stencil_texture, format)) = depth_stencil {
+        gl.framebuffer_texture_2d(
+            gl::DRAW_FRAMEBUFFER,
+            depth_stencil_attachment(format),
+            gl::TEXTURE_2D,
+            Some(depth_stencil_texture),
+            0,
+        );
+        if format.has_depth() {
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+        if format.has_stencil() {
+            mask |= gl::STENCIL_BUFFER_BIT;
+        }
+    }
+    gl.bind_framebuffer(gl::READ_FRAMEBUFFER, Some(multisample.framebuffer));
+    gl.blit_framebuffer(
+        0,
+        0,
+        size.width,
+        size.height,
+        0,
+        0,
+        size.width,
+        size.height,
+        mask,
+        gl::NEAREST,
+    );
+
+    // Restore the GL state
+    gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer(bound_fbos[0] as _));
+    gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbos[1] as _));
+    if scissor_enabled {
+        gl.enable(gl::SCISSOR_TEST);
+    }
+    if let Some(fbo) = resolve_fbo {
+        gl.delete_framebuffer(fbo);
+    }
+    debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+}
+
+/// Read back the pixels of a layer's color texture
+unsafe fn read_texture(
+    gl: &Gl,
+    texture: Option<gl::NativeTexture>,
+    texture_target: u32,
+    size: Size2D<i32, Viewport>,
+) -> LayerImage {
+    // Save the current GL state
+    let mut bound_fbo = [0];
+    gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbo);
+
+    // Read the texture through a temporary FBO
+    let read_fbo = gl.create_framebuffer().ok();
+    gl.bind_framebuffer(gl::READ_FRAMEBUFFER, read_fbo);
+    gl.framebuffer_texture_2d(
+        gl::READ_FRAMEBUFFER,
+        gl::COLOR_ATTACHMENT0,
+        texture_target,
+        texture,
+        0,
+    );
+    let mut data = vec![0; size.width as usize * size.height as usize * 4];
+    gl.read_pixels(
+        0,
+        0,
+        size.width,
+        size.height,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        PixelPackData::Slice(Some(&mut data)),
+    );
+
+    // Restore the GL state
+    gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbo[0] as _));
+    if let Some(fbo) = read_fbo {
+        gl.delete_framebuffer(fbo);
+    }
+    debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+
+    LayerImage {
+        width: size.width as u32,
+        height: size.height as u32,
+        data,
+    }
 }